@@ -3,6 +3,174 @@
 
 /* auto-generated by NAPI-RS */
 
+/**
+ * Searches content that arrives in chunks, such as stdin or another readable
+ * stream, one complete line at a time. Partial lines are buffered until the
+ * rest of the line arrives, and line numbers and byte offsets count from the
+ * start of the first chunk.
+ *
+ * Each line is matched on its own, so patterns cannot match across lines.
+ * `exclude_patterns`, `min_line_length`, the pattern rewriting options,
+ * `include_text`, `max_line_length`, `snippet_radius`, `include_match_bytes`,
+ * `captures`, `column_unit`, `tab_width`, `invert_match`, `max_results`,
+ * `zero_based`, `line_terminator`, `guard`, `guard_window`, `scope`,
+ * `field_delimiter` and `byte_map` apply; the remaining options are ignored.
+ * The guard and scope only see the current line.
+ */
+export class ReaderSearch {
+  /**
+   * Compile `patterns` for an incremental search combined according to
+   * `mode`. Throws if any pattern fails to compile.
+   */
+  constructor(patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null)
+  /**
+   * Search the lines completed by `chunk` and return the ones that match,
+   * in line order. A trailing partial line is kept for the next chunk.
+   */
+  push(chunk: Buffer): Array<LineMatch>
+  /**
+   * Search the final line if the content did not end with a line
+   * terminator. Call once all chunks have been pushed; later pushes are
+   * ignored.
+   */
+  finish(): Array<LineMatch>
+  /**
+   * Per-pattern results for everything pushed so far, combined according to
+   * `mode` like `search_buffer`. Returns an empty array if the content does
+   * not match. `lines` is never included; matching lines are returned by
+   * `push` and `finish` instead.
+   */
+  result(): Array<FilePatternMatches>
+}
+/**
+ * How content that looks binary (contains a NUL byte near the start) is
+ * handled.
+ */
+export const enum BinaryMode {
+  /** Binary content produces no results. */
+  Skip = 0,
+  /** Binary content is searched like any other content. */
+  Search = 1,
+  /**
+   * Binary content is searched after replacing invalid UTF-8 sequences
+   * with U+FFFD. Offsets then refer to the replaced content.
+   */
+  TextReplace = 2
+}
+/** Order of the per-line results within a file. */
+export const enum SortOrder {
+  /** By ascending line number. */
+  Ascending = 0,
+  /** By descending line number. */
+  Descending = 1,
+  /**
+   * In the order the matches were found: all lines matched by the first
+   * pattern, then the new lines matched by the second, and so on.
+   */
+  MatchOrder = 2
+}
+/** Whether `lines` has one entry per matching line or per match. */
+export const enum GroupBy {
+  /** One entry per line, listing all of its matches. */
+  Line = 0,
+  /**
+   * One entry per match, so a line with several matches appears several
+   * times.
+   */
+  Match = 1
+}
+/** How content is split into the records returned with matching lines. */
+export const enum RecordMode {
+  /** Each line is its own record. */
+  Line = 0,
+  /**
+   * Records are paragraphs: runs of non-empty lines separated by empty
+   * lines.
+   */
+  Paragraph = 1
+}
+/** Unit of the columns reported in `LineMatch` and `MatchRange`. */
+export const enum ColumnUnit {
+  /** Bytes of the (transcoded) UTF-8 content. */
+  Byte = 0,
+  /** Unicode scalar values, as counted by `[...str].length` in JavaScript. */
+  Char = 1,
+  /**
+   * UTF-16 code units, matching JavaScript string indices for
+   * `.length` and `.slice`.
+   */
+  Utf16 = 2
+}
+/** Where one pattern must match within a line, see `SearchOptions.anchors`. */
+export const enum Anchor {
+  /** Anywhere in the line. */
+  None = 0,
+  /** At the start of a line. */
+  LineStart = 1,
+  /** At the end of a line. */
+  LineEnd = 2,
+  /** The entire line. */
+  FullLine = 3
+}
+/** Unicode normalization form applied before matching. */
+export const enum Normalization {
+  /** Canonical composition, e.g. `é` as one code point. */
+  Nfc = 0,
+  /**
+   * Canonical decomposition, e.g. `é` as `e` followed by a combining
+   * accent.
+   */
+  Nfd = 1
+}
+/** How file content is loaded. */
+export const enum ReadMode {
+  /** Memory-map the file. Fails on files that cannot be mapped. */
+  Mmap = 0,
+  /** Read the whole file into memory. */
+  Read = 1,
+  /**
+   * Memory-map non-empty regular files and read everything else, or when
+   * mapping fails.
+   */
+  Auto = 2
+}
+/** Text encoding of the content being searched. */
+export const enum Encoding {
+  /** UTF-8 (or any ASCII-compatible encoding), searched in place. */
+  Utf8 = 0,
+  /** UTF-16 little-endian, transcoded to UTF-8 before searching. */
+  Utf16Le = 1,
+  /** UTF-16 big-endian, transcoded to UTF-8 before searching. */
+  Utf16Be = 2,
+  /** ISO-8859-1, transcoded to UTF-8 before searching. */
+  Latin1 = 3
+}
+/** How lines that are not valid UTF-8 are returned. */
+export const enum LossyMode {
+  /** Invalid sequences are replaced with U+FFFD. */
+  Lossy = 0,
+  /**
+   * Such lines are left out of the results, as if excluded. Context lines
+   * are left empty and `record_text` is left out.
+   */
+  Skip = 1,
+  /**
+   * `text` holds the raw bytes base64-encoded, with `text_base64` set;
+   * likewise for context lines and `record_text`.
+   */
+  Base64 = 2
+}
+/** How per-pattern results are combined into a file-level match. */
+export const enum MatchMode {
+  /**
+   * Every pattern must match somewhere in the file. Set
+   * `SearchOptions.within_lines` to 0 to require every pattern on the same
+   * line instead.
+   */
+  And = 0,
+  /** At least one pattern must match somewhere in the file. */
+  Or = 1
+}
 export interface PatternMatch {
   /** Index into the input patterns array (0-based) */
   patternIndex: number
@@ -14,10 +182,834 @@ export interface PatternMatch {
 export interface FilePatternMatches {
   /** Absolute file path */
   filePath: string
-  /** Total number of lines in the file */
+  /**
+   * Total number of lines in the file, for example to compute match
+   * density together with `matching_lines`. 0 when
+   * `SearchOptions.include_total_lines` is false.
+   */
   totalLines: number
+  /**
+   * Number of matches across all patterns. Matches of different patterns
+   * at the same position count once. For inverted searches, the number of
+   * selected lines.
+   */
+  totalMatches: number
+  /** Number of distinct lines in the patterns' `line_numbers` */
+  matchingLines: number
   /** Per-pattern match data. Only patterns with >= 1 match are included. */
   patterns: Array<PatternMatch>
+  /**
+   * Per-line match details across all included patterns, sorted by line.
+   * Only present when `SearchOptions.include_lines` is true.
+   */
+  lines?: Array<LineMatch>
+  /**
+   * Runs of consecutive matching lines, in the order of `lines`. Only
+   * present when `SearchOptions.collapse_ranges` is true.
+   */
+  lineRanges?: Array<LineRange>
+  /**
+   * True if `SearchOptions.deadline_ms` passed while this file was being
+   * searched, so the results only cover what was found until then.
+   */
+  partial: boolean
+  /**
+   * True if a limit left matches out of the results: `max_results`,
+   * `max_matches_per_line`, `head_bytes` cutting the file, or
+   * `deadline_ms` (see `partial`). Lets callers tell "the first 1000 of
+   * many" from complete results.
+   */
+  truncated: boolean
+  /**
+   * Whether `SearchOptions.max_total_text_bytes` ran out, leaving the
+   * `text` of the remaining lines empty and dropping their other strings.
+   * Only present when that budget is set.
+   */
+  textTruncated?: boolean
+  /**
+   * Why the file could not be searched, such as a missing file or a
+   * permission error. Only set on the entries that
+   * `SearchOptions.report_errors` adds for failed files, which have no
+   * matches.
+   */
+  error?: string
+  /**
+   * Diagnostics about the search. Only present when
+   * `SearchOptions.include_stats` is true.
+   */
+  stats?: SearchStats
+  /**
+   * Index of the searched string in the `inputs` of `search_strings`. Only
+   * present on its results.
+   */
+  inputIndex?: number
+}
+/**
+ * Timing and size diagnostics for one searched file. These only describe
+ * the search and never affect its results.
+ */
+export interface SearchStats {
+  /**
+   * Number of bytes matched against, after decompression and the line
+   * window are applied
+   */
+  bytesScanned: number
+  /** Time spent matching the patterns, in microseconds */
+  matchTimeUs: number
+  /**
+   * Time spent compiling the patterns for this call, in microseconds. 0
+   * when the compiled patterns came from the regex cache.
+   */
+  compileTimeUs: number
+}
+/** A run of consecutive matching lines. */
+export interface LineRange {
+  /** First line of the run */
+  startLine: number
+  /** Last line of the run (inclusive) */
+  endLine: number
+  /**
+   * Text of the first line of the run. Only present when
+   * `SearchOptions.include_text` is true.
+   */
+  text?: string
+  /**
+   * Text of every line of the run. Only present when
+   * `SearchOptions.include_range_lines` is true.
+   */
+  lines?: Array<string>
+}
+/**
+ * Position of one match. Columns are in bytes unless
+ * `SearchOptions.column_unit` or `SearchOptions.tab_width` is set.
+ */
+export interface MatchRange {
+  /** 0-based start column of the match, in bytes from the start of the line */
+  start: number
+  /**
+   * End column of the match (exclusive), in bytes from the start of the
+   * line. May extend past the line for matches that span a line ending.
+   */
+  end: number
+  /**
+   * Line number of the line the match ends on, numbered like
+   * `LineMatch.line_number`. Only present for matches that span a line
+   * ending; a match whose last byte is the line terminator ends on its
+   * starting line.
+   */
+  endLine?: number
+  /**
+   * End column of the match (exclusive) in bytes from the start of
+   * `end_line`. Only present when `end_line` is.
+   */
+  endColumn?: number
+  /**
+   * The exact matched bytes, base64 encoded (`Buffer.from(bytes,
+   * 'base64')`), for binary or mixed content where `text` is decoded
+   * lossily. Only present when `SearchOptions.include_match_bytes` is true.
+   */
+  bytes?: string
+  /**
+   * Text of each named capture group that took part in the match, by group
+   * name, decoded as UTF-8 lossily. Only present when
+   * `SearchOptions.captures` is true and the pattern that found the match
+   * has named groups.
+   */
+  captures?: Record<string, string>
+}
+export interface LineMatch {
+  /** 1-based line number */
+  lineNumber: number
+  /** Byte offset from the start of the file to the first match on this line */
+  byteOffset: number
+  /**
+   * 0-based column of the first match on this line, in bytes from the
+   * start of the line (or in `SearchOptions.column_unit`)
+   */
+  column: number
+  /**
+   * Every match on this line from all patterns, sorted by start column.
+   * Empty for inverted searches.
+   */
+  matches: Array<MatchRange>
+  /**
+   * Indices into the input patterns array of the patterns that matched
+   * this line, sorted. For inverted searches, the patterns that matched
+   * the selected line (only possible with `MatchMode.And`).
+   */
+  matchedPatterns: Array<number>
+  /**
+   * Text of the line without its line ending, decoded as UTF-8 according
+   * to `SearchOptions.lossy_mode`. Only present when
+   * `SearchOptions.include_text` is true.
+   */
+  text?: string
+  /**
+   * Column within the line where `text` starts. Only present when
+   * `SearchOptions.max_line_length` or `SearchOptions.snippet_radius` is
+   * set; non-zero when `text` does not start at the start of the line.
+   */
+  textOffset?: number
+  /**
+   * Whether `text` was cut at either end to `SearchOptions.max_line_length`
+   * or `SearchOptions.snippet_radius`. Only present when one of them is set.
+   */
+  truncated?: boolean
+  /**
+   * Number of leading space and tab bytes of the line, for aligning
+   * snippets. Counted on the whole line, even when `text` is cut. Only
+   * present when `SearchOptions.include_indent` is true.
+   */
+  indent?: number
+  /**
+   * Text of the line after replacing every match of the first pattern
+   * with `SearchOptions.replacement`. Only present on matching lines when
+   * a replacement is given.
+   */
+  replacedText?: string
+  /**
+   * Line number of the first line of the record containing this line.
+   * Only present when `SearchOptions.record_mode` is `Paragraph`.
+   */
+  recordLine?: number
+  /**
+   * Text of the record containing this line, limited to
+   * `SearchOptions.max_line_length` bytes around the first match. Only
+   * present on the first returned line of each record when
+   * `SearchOptions.record_mode` is `Paragraph`.
+   */
+  recordText?: string
+  /**
+   * Up to `SearchOptions.context_before` lines preceding this line. Lines
+   * that are themselves matches or already part of the previous match's
+   * context are not repeated.
+   */
+  contextBefore?: Array<string>
+  /**
+   * Up to `SearchOptions.context_after` lines following this line, stopping
+   * before the next matching line.
+   */
+  contextAfter?: Array<string>
+  /**
+   * The line with its context lines joined by `
+  `, in place of
+   * `context_before` and `context_after`. Only present when
+   * `SearchOptions.context_as_block` is true.
+   */
+  block?: string
+  /**
+   * 0-based index of this line among the lines of `block`. Only present
+   * with `block`.
+   */
+  blockLine?: number
+  /**
+   * False for a context line returned as its own entry, true for a
+   * matching line. Only present when `SearchOptions.context_as_lines` is
+   * true.
+   */
+  isMatch?: boolean
+  /**
+   * Text of the nearest line above this one matching
+   * `SearchOptions.heading_pattern`. Only present when a heading pattern
+   * is given and such a line exists.
+   */
+  heading?: string
+  /**
+   * True if `text` holds the line's raw bytes base64-encoded because they
+   * are not valid UTF-8. Only present with `LossyMode.Base64`.
+   */
+  textBase64?: boolean
+  /**
+   * True if every line of `context_before`, `context_after` or `block`
+   * holds its raw bytes base64-encoded because at least one of them is not
+   * valid UTF-8. Only present with `LossyMode.Base64`.
+   */
+  contextBase64?: boolean
+  /**
+   * True if `record_text` holds the record's raw bytes base64-encoded
+   * because they are not valid UTF-8. Only present with
+   * `LossyMode.Base64`.
+   */
+  recordTextBase64?: boolean
+}
+/**
+ * The lines of a `search_file_flat` result as parallel typed arrays, one
+ * entry per matching line, which are much cheaper to transfer to a worker
+ * than an array of objects.
+ */
+export interface FlatMatches {
+  /** `LineMatch.line_number` of each line */
+  lines: Uint32Array
+  /** `LineMatch.byte_offset` of each line */
+  offsets: Float64Array
+  /** `LineMatch.column` of each line */
+  columns: Uint32Array
+  /**
+   * `LineMatch.text` of every line concatenated, as UTF-8. Only present
+   * when `SearchOptions.include_text` is true.
+   */
+  text?: Buffer
+  /**
+   * Start of each line's text in `text`, plus the end of the last one, so
+   * line `i` is `text[text_offsets[i]..text_offsets[i + 1]]`. Only present
+   * with `text`.
+   */
+  textOffsets?: Uint32Array
+}
+export interface CacheInfo {
+  /** Whether any compiled pattern set is cached */
+  hasEntry: boolean
+  /**
+   * Key of the most recently used pattern set: the patterns, each tagged
+   * and length-prefixed, then a NUL character and the compile settings
+   */
+  cacheKey?: string
+  /** Keys of all cached pattern sets, most recently used first */
+  cacheKeys: Array<string>
+}
+/**
+ * Optional search settings shared by all search functions. Every field may be
+ * omitted, in which case the documented default is used.
+ */
+export interface SearchOptions {
+  /**
+   * If true, each pattern is matched case-insensitively when it contains no
+   * uppercase characters, and case-sensitively otherwise (like ripgrep's
+   * `--smart-case`). Takes precedence over `case_insensitive`.
+   * Default: false.
+   */
+  smartCase?: boolean
+  /**
+   * Regex patterns whose matching lines are excluded. Matches on a line
+   * matching any of these are discarded before the AND/OR check, so a
+   * pattern that only matches on excluded lines counts as not matching.
+   * Exclusion is scoped to the line: other lines of the file still match.
+   * Compiled with the same flags as `patterns`. Default: none.
+   */
+  excludePatterns?: Array<string>
+  /**
+   * Lines shorter than this many bytes, without their line ending, are
+   * excluded like lines matching `exclude_patterns`, to skip noise on very
+   * short lines. Default: 0.
+   */
+  minLineLength?: number
+  /**
+   * Regex tested right after each match, as a substitute for negative
+   * lookahead ("X not followed by Y"): a match is discarded when the guard
+   * matches within `guard_window` bytes after its end. `^` anchors to the
+   * match end. Compiled with the same flags as `patterns`. Default: none.
+   */
+  guard?: string
+  /**
+   * Number of bytes after each match that `guard` is tested against.
+   * Default: 64.
+   */
+  guardWindow?: number
+  /**
+   * Regex whose matches are the only regions searched, such as comments
+   * (`/\*[\s\S]*?\*/`) or strings: a match is kept only if it starts
+   * inside a match of `scope`. The scope is matched once over the whole
+   * content. Compiled with the same flags as `patterns`. Default: none.
+   */
+  scope?: string
+  /**
+   * Field delimiter byte for delimited data, such as 44 (`,`) for CSV or 9
+   * (`	`) for TSV: a match is kept only if it starts and ends on a field
+   * boundary, that is next to this byte or at the start or end of a line.
+   * Default: none.
+   */
+  fieldDelimiter?: number
+  /**
+   * If true, patterns only match whole words, as if each were wrapped in
+   * `(?:...)`. Word boundaries follow the `unicode` flag. Default: false.
+   */
+  wholeWord?: boolean
+  /**
+   * If true, each pattern must match an entire line, as if wrapped in
+   * `(?m:^(?:...)$)`. Combines with `literal` and `whole_word`; lines ending
+   * in CRLF match without their ``. Default: false.
+   */
+  fullLineMatch?: boolean
+  /**
+   * If true, each pattern must match at the start of a line, as if wrapped
+   * in `(?m:^(?:...))`, for fast "lines starting with" filters. Unlike
+   * `full_line_match` the match may end anywhere on the line. Default: false.
+   */
+  linePrefix?: boolean
+  /**
+   * Names for the patterns, in pattern order, that `search_file_expr`
+   * expressions can use instead of pattern indices. Default: none.
+   */
+  patternNames?: Array<string>
+  /**
+   * Where each pattern must match within a line, in pattern order, for
+   * anchoring some patterns but not others. Patterns without an entry are
+   * not anchored. Applied on top of `full_line_match` and `line_prefix`.
+   * Default: none.
+   */
+  anchors?: Array<Anchor>
+  /**
+   * If true, `^` and `$` match at the start and end of every line; if
+   * false, only at the start and end of the content (or of each line for
+   * `ReaderSearch`, which matches lines on their own). `.` never matches
+   * the line terminator either way. Results are still reported per line,
+   * on the line where each match starts. `full_line_match` always anchors
+   * to lines. Default: true.
+   */
+  multiLine?: boolean
+  /**
+   * If true, `.` also matches the line terminator, so patterns such as
+   * `START.*END` can span lines. A match that spans lines is reported once,
+   * on the line where it starts: only that line counts towards
+   * `line_numbers` and `matching_lines`, and its `MatchRange.end` extends
+   * past the line. Default: false.
+   */
+  dotAll?: boolean
+  /**
+   * If true, patterns are plain strings rather than regexes: all regex
+   * metacharacters are escaped, so `a.b` only matches the text `a.b`.
+   * Default: false.
+   */
+  literal?: boolean
+  /**
+   * Ripgrep-style short flags as one string, such as `"wiF"`, for terse
+   * call sites: `i` case-insensitive, `S` smart case, `w` whole word, `x`
+   * full line match, `F` literal and `s` dot-all. Each turns its setting on
+   * in addition to the other arguments and options, so no combination
+   * conflicts: `S` wins over `i` and `x` over `line_prefix`, as their
+   * options do. Unknown characters are an error. Default: none.
+   */
+  flags?: string
+  /**
+   * Overrides the `unicode` argument for `.`, `\w`, `\d`, `\s`, `` and
+   * character classes. Default: the `unicode` argument.
+   */
+  unicodeDot?: boolean
+  /**
+   * Overrides the `unicode` argument for case folding: Unicode simple case
+   * folding if true, ASCII letters only if false. When this differs from
+   * `unicode_dot`, case-insensitive patterns are rewritten with explicit
+   * folded classes, which makes them slower to compile. Default: the
+   * `unicode` argument.
+   */
+  unicodeCase?: boolean
+  /**
+   * If true, each result also carries `lines` with per-line match details.
+   * Default: false.
+   */
+  includeLines?: boolean
+  /**
+   * Maximum number of matching lines reported per file. Each pattern's
+   * `line_numbers` and the `lines` array keep at most this many lines, the
+   * earliest ones, and `frequency` only counts matches on the kept lines.
+   * Scanning for a pattern stops once its limit is reached. Default: no limit.
+   */
+  maxResults?: number
+  /**
+   * If true, `max_results` keeps the last matching lines instead of the
+   * earliest, for tailing logs. The content is still scanned from the
+   * start, since line numbers are counted from there, and scanning no
+   * longer stops at the limit. To scan only a tail window, set
+   * `start_byte` to shortly before the end instead; line numbers then
+   * count from the window. Ignored by `search_file_expr`,
+   * `search_file_stream` and `ReaderSearch`. Default: false.
+   */
+  fromEnd?: boolean
+  /**
+   * Number of entries of `lines` to leave out, in the order they are
+   * returned, for paging through large results. Only `lines` is paged;
+   * `patterns` and the counts still describe every matching line, e.g. to
+   * show "page X of Y". Default: 0.
+   */
+  skip?: number
+  /**
+   * Maximum number of entries of `lines` returned after `skip`. Unlike
+   * `max_results`, this does not limit `patterns` or the counts.
+   * Default: no limit.
+   */
+  limit?: number
+  /**
+   * If true, each entry in `lines` carries the line's `text`. Requires
+   * `include_lines`. Default: false.
+   */
+  includeText?: boolean
+  /**
+   * If true, each entry in `lines` with `text` also carries the line's
+   * `indent`. Requires `include_text`. Default: false.
+   */
+  includeIndent?: boolean
+  /**
+   * How lines that are not valid UTF-8 are returned: with replacement
+   * characters, left out of the results, or base64-encoded. Applies to
+   * `text`, the context lines, `block` and `record_text`; headings and
+   * other text are always decoded lossily. Default: `Lossy`.
+   */
+  lossyMode?: LossyMode
+  /**
+   * If false, `total_lines` is reported as 0, saving the extra pass over
+   * the content that counts its lines. Default: true.
+   */
+  includeTotalLines?: boolean
+  /**
+   * If true, `search_files` and `search_dir` only report how much each
+   * file matches, for ranking files before drilling in: `matching_lines`,
+   * `total_matches` and `total_lines`, plus each pattern's `frequency`
+   * with empty `line_numbers`. No `lines` or `line_ranges` are built,
+   * regardless of the options requesting them, and the matches are only
+   * counted, unless `invert_match`, `within_lines` with `And` or
+   * `from_end` with `max_results` need their line numbers. Default: false.
+   */
+  summaryOnly?: boolean
+  /**
+   * If true, each entry in `matches` carries the exact matched `bytes`,
+   * base64 encoded. Requires `include_lines`. Default: false.
+   */
+  includeMatchBytes?: boolean
+  /**
+   * If true, each entry in `matches` carries the text of the named capture
+   * groups of the pattern that found it, e.g. to extract a timestamp and
+   * level from a log line. Requires `include_lines`. Default: false.
+   */
+  captures?: boolean
+  /**
+   * If true, each result also carries `line_ranges`: the matching lines
+   * merged into runs of consecutive lines, for folding large matching
+   * blocks. Runs carry the text of their first line with `include_text`.
+   * Does not require `include_lines`. Default: false.
+   */
+  collapseRanges?: boolean
+  /**
+   * If true, each of the `line_ranges` carries the text of all of its
+   * lines. Default: false.
+   */
+  includeRangeLines?: boolean
+  /**
+   * If true, each result carries `stats` with the number of bytes scanned
+   * and the time spent compiling and matching, for diagnosing
+   * performance. Default: false.
+   */
+  includeStats?: boolean
+  /**
+   * If false, matches of different patterns at the same position are not
+   * merged: each is listed in `matches` and counted in `total_matches`.
+   * Lines are still reported once. Merging sorts every match position, so
+   * turning it off saves that work when patterns cannot overlap, at the
+   * cost of larger results when they do. Default: true.
+   */
+  dedupPositions?: boolean
+  /**
+   * With `Paragraph`, each entry in `lines` also carries the paragraph
+   * containing it (`record_line` and `record_text`), for searching blocks
+   * such as email headers. Matching still works on lines. Requires
+   * `include_lines`; ignored by `search_file_stream` and `ReaderSearch`.
+   * Default: `Line`.
+   */
+  recordMode?: RecordMode
+  /**
+   * With `Match`, `lines` has one entry per match instead of per line, each
+   * with its own `column`, `byte_offset`, single-element `matches` and the
+   * patterns that found that match. `max_results` still counts lines, and
+   * context lines go with the first and last entry of a line. Ignored for
+   * inverted searches and by `search_file_stream` and `ReaderSearch`.
+   * Default: `Line`.
+   */
+  groupBy?: GroupBy
+  /**
+   * Limit in bytes on `text` and context lines, so very long lines (such
+   * as minified code) stay small. Longer lines are cut without splitting
+   * UTF-8 characters. `text` keeps the first match when possible, starting
+   * at it if it would not fit otherwise; see `text_offset` and `truncated`.
+   * Default: no limit.
+   */
+  maxLineLength?: number
+  /**
+   * If set, `text` is only the first match on the line plus up to this
+   * many bytes on each side, for autocomplete-style previews. The snippet
+   * never splits a UTF-8 character; see `text_offset` and `truncated`.
+   * Takes precedence over `max_line_length` for `text`. Default: the whole
+   * line.
+   */
+  snippetRadius?: number
+  /**
+   * Budget in bytes for the strings of all returned lines of a file
+   * together, as a ceiling on the strings allocated for large results.
+   * Counts `text`, `replaced_text`, `record_text`, the context lines,
+   * `block`, `heading` and the `bytes` and `captures` of the matches.
+   * Lines keep their strings in order while they fit; once a line's
+   * strings do not, it and all later lines get an empty `text` and lose
+   * the other strings, and `text_truncated` is set. Line numbers and match
+   * positions are unaffected. Default: no limit.
+   */
+  maxTotalTextBytes?: number
+  /**
+   * Number of lines of context to return before each matching line, like
+   * `grep -B`. Requires `include_lines`. Default: 0.
+   */
+  contextBefore?: number
+  /**
+   * Number of lines of context to return after each matching line, like
+   * `grep -A`. Requires `include_lines`. Default: 0.
+   */
+  contextAfter?: number
+  /**
+   * If true, the context lines are returned joined with the line itself
+   * into one `block` string, with `block_line` giving the line's position
+   * in it, instead of as `context_before` and `context_after`. Requires
+   * `include_lines`. Default: false.
+   */
+  contextAsBlock?: boolean
+  /**
+   * If true, each context line is returned as its own entry in `lines`,
+   * next to the line it belongs to, with `is_match` false, `text` set and
+   * no matches; matching lines get `is_match` true. Suits renderers that
+   * want one flat list. Ignored with `context_as_block`. Requires
+   * `include_lines`. Default: false.
+   */
+  contextAsLines?: boolean
+  /**
+   * Regex for heading lines, such as `^\s*(fn|class|def)`: each entry in
+   * `lines` gets `heading`, the text of the nearest line above it that
+   * matches, as a "match is inside fn foo" breadcrumb. Compiled with the
+   * same flags as `patterns`. Requires `include_lines`. Default: none.
+   */
+  headingPattern?: string
+  /**
+   * If true, select the lines that do *not* match, like `grep -v`. With
+   * `Or`, a line is selected when no pattern matches it; with `And`, when
+   * at least one pattern does not match it. Each pattern's `line_numbers`
+   * lists the selected lines it does not match and `frequency` counts those
+   * lines. In `lines`, `byte_offset` and `column` point at the line start.
+   * Default: false.
+   */
+  invertMatch?: boolean
+  /**
+   * Files larger than this many bytes are skipped by `search_dir` without
+   * being opened. Default: no limit.
+   */
+  maxFileSize?: number
+  /**
+   * Files smaller than this many bytes are skipped by `search_dir` without
+   * being opened. Default: no limit.
+   */
+  minFileSize?: number
+  /**
+   * Files last modified at or before this time, in milliseconds since the
+   * Unix epoch (`Date.getTime()`), are skipped by `search_dir` without
+   * being opened. Default: no limit.
+   */
+  modifiedAfter?: number
+  /**
+   * If true, `search_dir` skips paths ignored by `.gitignore` (inside git
+   * repositories), `.ignore` files and git's global and repository
+   * excludes, like ripgrep. Default: true.
+   */
+  respectGitignore?: boolean
+  /**
+   * If true, `search_dir` also searches hidden files and directories (names
+   * starting with `.`). Default: false.
+   */
+  hidden?: boolean
+  /**
+   * If true, `search_dir` follows symbolic links to files and directories.
+   * Links that form a cycle or cannot be resolved are skipped. If false,
+   * symbolic links are skipped. Default: false.
+   */
+  followSymlinks?: boolean
+  /**
+   * If true, `search_files` and `search_dir` also return an entry for each
+   * file that could not be opened or mapped, with `error` set and no
+   * matches, instead of leaving it out. Default: false.
+   */
+  reportErrors?: boolean
+  /**
+   * How content that looks binary is handled. Content is treated as binary
+   * if its first 8 KiB contain a NUL byte (see `binary_scan_bytes` and
+   * `binary_threshold`). Default: `Skip`.
+   */
+  binaryMode?: BinaryMode
+  /**
+   * Number of leading bytes inspected when deciding whether content is
+   * binary. Default: 8192.
+   */
+  binaryScanBytes?: number
+  /**
+   * If set, content is binary when more than this fraction (0 to 1) of the
+   * inspected bytes are control bytes other than tab, line feed, vertical
+   * tab, form feed, carriage return and escape, instead of when any NUL
+   * byte is present. Default: none.
+   */
+  binaryThreshold?: number
+  /**
+   * Encoding of the content. Anything other than `Utf8` is transcoded to
+   * UTF-8 before searching; a leading UTF-16 byte order mark is dropped
+   * and invalid UTF-16 is replaced with U+FFFD. Byte offsets and columns
+   * then refer to the transcoded UTF-8 content, not the original bytes.
+   * Default: `Utf8`.
+   */
+  encoding?: Encoding
+  /**
+   * How files are loaded. `Read` and `Auto` support files that cannot be
+   * memory-mapped, such as those under `/proc` and named pipes; results
+   * are the same in every mode. Default: `Mmap`.
+   */
+  readMode?: ReadMode
+  /**
+   * If set, both the content and the patterns are normalized to this
+   * Unicode form before matching, so text in NFD matches patterns in NFC
+   * and vice versa. The content is copied and decoded as UTF-8 (invalid
+   * sequences become U+FFFD); byte offsets and columns then refer to the
+   * normalized content. Default: none.
+   */
+  normalize?: Normalization
+  /**
+   * A 256-entry table every content byte is mapped through before
+   * matching, for case folding legacy single-byte encodings such as
+   * CP-1252. The ASCII literals and `\xHH` escapes of the patterns are
+   * mapped the same way; other non-ASCII pattern characters are not, so
+   * write non-ASCII bytes as `\xHH`. Only applies when `unicode` is false.
+   * Line text then shows the mapped bytes. A table without exactly 256
+   * entries is an error. Default: none.
+   */
+  byteMap?: Array<number>
+  /**
+   * If true, gzip-compressed content (starting with the bytes `1f 8b`) is
+   * decompressed in memory before searching, and line numbers and offsets
+   * refer to the decompressed content. Content that fails to decompress is
+   * searched as is. Default: true.
+   */
+  decompress?: boolean
+  /**
+   * 1-based first line to search. Lines before it are neither matched nor
+   * returned, but line numbers and offsets stay absolute. Default: 1.
+   */
+  startLine?: number
+  /** 1-based last line to search (inclusive). Default: the last line. */
+  endLine?: number
+  /**
+   * Byte offset of the start of the region to search, for when an index
+   * already narrows down where matches are. Only the region is searched:
+   * line numbers count from its first line (see `base_line`),
+   * `start_line` and `end_line` select lines within it, and `total_lines`
+   * counts its lines, while `byte_offset` stays relative to the whole
+   * content. Partial lines at either edge are searched as they are.
+   * Offsets refer to the decompressed and transcoded content. Clamped to
+   * the content length; negative offsets are an error. Default: 0.
+   */
+  startByte?: number
+  /**
+   * Length in bytes of the region starting at `start_byte`, clamped to the
+   * end of the content; a negative length is an error. Default: the rest
+   * of the content.
+   */
+  byteLength?: number
+  /**
+   * If set, only the first `head_bytes` bytes of the file as stored are
+   * searched, for cheaply classifying files by a shebang or magic header.
+   * Unlike `byte_length`, the rest of the file is never decompressed or
+   * transcoded, so compressed files cut this way usually fail to
+   * decompress and are searched as they are stored. Default: the whole file.
+   */
+  headBytes?: number
+  /**
+   * Line number of the first line of the region selected by `start_byte`,
+   * so line numbers can be reported relative to the whole file when it is
+   * known. Together with `start_line` it must keep the first searched line
+   * number within `u32`. Default: 1.
+   */
+  baseLine?: number
+  /**
+   * With `MatchMode.And`, only lines that have a match of every pattern
+   * within this many lines of them are returned (0 means on the same
+   * line), and a file matches only if such a line exists. Per-pattern
+   * results keep only the matches near a returned line. Ignored with
+   * `MatchMode.Or` and `invert_match`. Default: no proximity limit.
+   */
+  withinLines?: number
+  /**
+   * Byte that ends a line, e.g. 13 for CR-only files or 0 for
+   * NUL-delimited records. Applies to line numbers, line text and context,
+   * and to `^`, `$` and `.` in patterns. A trailing `` is only trimmed
+   * from line text when this is `
+  `. With 0, NUL bytes do not make
+   * content count as binary. Default: 10 (`
+  `).
+   */
+  lineTerminator?: number
+  /**
+   * Order of `lines`. `Descending` also reverses each pattern's
+   * `line_numbers`. `max_results` keeps the lowest line numbers, except
+   * with `MatchOrder`, where it keeps the first lines found.
+   * Default: `Ascending`.
+   */
+  sort?: SortOrder
+  /**
+   * Time budget for the search in milliseconds, measured from the start
+   * of matching. When it runs out, the search stops and returns what was
+   * found so far with `partial` set; with `MatchMode.And`, a file whose
+   * patterns were not all found by then does not match. Files not yet
+   * started are skipped, and `count_matching_lines` and
+   * `search_file_stream` stop counting or calling back. Default: no limit.
+   */
+  deadlineMs?: number
+  /**
+   * If true, reported line numbers (`line_numbers` and `line_number`)
+   * start at 0 instead of 1. `start_line` and `end_line` stay 1-based.
+   * Default: false.
+   */
+  zeroBased?: boolean
+  /**
+   * Unit of `column`, `text_offset` and the `matches` columns: bytes,
+   * Unicode characters, or UTF-16 code units to line up with JavaScript
+   * string indices into `text`. Invalid UTF-8 counts like its U+FFFD
+   * replacement in `text`. `byte_offset` is always in bytes.
+   * Default: `Byte`.
+   */
+  columnUnit?: ColumnUnit
+  /**
+   * If set, columns are visual columns for editors and terminals that
+   * render tabs as several columns: each tab advances to the next multiple
+   * of this width, and other characters count in `column_unit`. Applies
+   * wherever `column_unit` does. Default: none (tabs count as one).
+   */
+  tabWidth?: number
+  /**
+   * If true, matches may overlap: after each match, the search resumes one
+   * byte after its start rather than at its end, so `aa` matches `aaaa`
+   * three times instead of twice. Affects `frequency`, `total_matches` and
+   * `matches`, not which lines match. Slower, since every position is
+   * retried. Ignored by `count_matching_lines`, `file_matches`,
+   * `search_file_stream` and `ReaderSearch`. Default: false.
+   */
+  overlapping?: boolean
+  /**
+   * Maximum number of matches of each pattern collected per line. Once a
+   * pattern reaches it, scanning for that pattern resumes at the next line,
+   * which keeps very long lines with many matches (such as minified code)
+   * cheap. `frequency`, `total_matches` and `matches` only count the
+   * collected matches. Ignored by `count_matching_lines`, `file_matches`,
+   * `search_file_stream` and `ReaderSearch`. Default: no limit.
+   */
+  maxMatchesPerLine?: number
+  /**
+   * Replacement for a preview of search-and-replace: each entry in `lines`
+   * gets `replaced_text`, the line with every match of the first pattern
+   * replaced. `$1`, `$name` and `${name}` refer to capture groups and `$$`
+   * is a literal `$`. Only matches within the line are replaced; nothing
+   * is written to disk. Default: none.
+   */
+  replacement?: string
+  /**
+   * Approximate limit, in bytes, on the size of each compiled regex.
+   * Patterns that exceed it fail to compile. Raise it for very large
+   * alternations. Default: 10 MiB.
+   */
+  sizeLimit?: number
+  /**
+   * Approximate limit, in bytes, on the cache used by each regex's lazy
+   * DFA. When exhausted, matching falls back to a slower engine.
+   * Default: 2 MiB.
+   */
+  dfaSizeLimit?: number
 }
 /**
  * Search a file for matches using AND semantics across regex patterns.
@@ -28,11 +1020,339 @@ export interface FilePatternMatches {
  * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
  *   use Unicode classes. If false, raw byte mode for maximum performance.
  * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
  *
  * Returns a single-element array with match data, or an empty array on
  * no match / error.
  */
-export declare function searchFileAnd(filePath: string, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean): Array<FilePatternMatches>
+export declare function searchFileAnd(filePath: string, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Search a file for matches, combining the patterns according to `mode`.
+ *
+ * - `file_path`: Absolute file path to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in the file,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
+ *
+ * Compiled regexes are kept in a thread-local cache, so repeated calls with
+ * the same patterns and flags skip compilation.
+ *
+ * Returns a single-element array with match data, or an empty array on
+ * no match / error.
+ */
+export declare function searchFile(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Same as `search_file`, but returns the results serialized as a JSON
+ * string, for large result sets where converting every line to a JavaScript
+ * object is slower than one `JSON.parse`. The JSON has the same shape and
+ * property names as the objects `search_file` returns.
+ *
+ * Returns `"[]"` on no match / error.
+ */
+export declare function searchFileJson(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): string
+/**
+ * Same as `search_file`, but returns the matching lines as parallel typed
+ * arrays (see `FlatMatches`) instead of `LineMatch` objects, for zero-copy
+ * transfer to a worker. `include_lines` is implied; the per-file counts and
+ * per-pattern results are not returned.
+ *
+ * Returns `null` on no match / error.
+ */
+export declare function searchFileFlat(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): FlatMatches | null
+/**
+ * Same as `search_file`, but reports failures instead of returning an empty
+ * array. Throws if the file does not exist, cannot be read or mapped, or if
+ * any pattern (or exclude pattern) fails to compile; the error message names
+ * the file or the offending pattern index and the regex error.
+ *
+ * Returns a single-element array with match data, or an empty array if the
+ * file genuinely does not match.
+ */
+export declare function searchFileChecked(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Search a file and pass each matching line to `callback` as soon as it is
+ * found, instead of building the full result list. This is a synchronous
+ * visitor: `callback` runs on the calling thread during the scan, which
+ * blocks the event loop until the search is done, so large files are best
+ * streamed from a worker. Returns the number of lines passed to `callback`.
+ *
+ * - `file_path`: Absolute path to the file to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in the file,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `callback`: Called synchronously with each `LineMatch` in line order.
+ *   Returning `false` stops the search.
+ * - `options`: Optional extra settings (see `SearchOptions`). `include_text`,
+ *   `max_results` and the line window apply; context lines, `invert_match`,
+ *   `within_lines`, `sort` and `replacement` are ignored.
+ *
+ * Throws on the same failures as `search_file_checked`, or if `callback`
+ * throws.
+ */
+export declare function searchFileStream(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, callback: (arg: LineMatch) => boolean | null, options?: SearchOptions | undefined | null): number
+/**
+ * Search an in-memory buffer, combining the patterns according to `mode`.
+ * Behaves exactly like `search_file` on a file with the same content, and
+ * shares its thread-local regex cache.
+ *
+ * - `data`: Content to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in the buffer,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
+ *
+ * Returns a single-element array with match data (with an empty `file_path`),
+ * or an empty array on no match / error.
+ */
+export declare function searchBuffer(data: Buffer, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Search several in-memory strings, such as open editor documents, in
+ * parallel, combining the patterns according to `mode`. Each string behaves
+ * exactly like `search_buffer` on its content.
+ *
+ * - `inputs`: Strings to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in a string,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
+ *
+ * Returns an array of `FilePatternMatches` for the matching strings, in
+ * input order, each with `input_index` set and an empty `file_path`.
+ * Returns an empty array on no match / error.
+ */
+export declare function searchStrings(inputs: Array<string>, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * A file kept open, memory-mapped or read into memory, so that several
+ * searches can reuse it without opening it again. Created with `open_file`;
+ * the file stays open until the handle is garbage collected.
+ */
+export class FileHandle {
+  /** Path the file was opened with */
+  get filePath(): string
+}
+/**
+ * Open a file for repeated searches with `search_handle`, for running
+ * several pattern sets against the same large file.
+ *
+ * - `file_path`: Absolute path to the file
+ * - `options`: Optional extra settings (see `SearchOptions`). Only
+ *   `read_mode` applies.
+ *
+ * Throws if the file does not exist or cannot be read or mapped.
+ */
+export declare function openFile(filePath: string, options?: SearchOptions | undefined | null): FileHandle
+/**
+ * Search a file opened with `open_file`. Behaves exactly like `search_file`
+ * on that file, and shares its thread-local regex cache.
+ *
+ * - `handle`: File returned by `open_file`
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in the file,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). `read_mode` is
+ *   ignored.
+ *
+ * Returns a single-element array with match data, or an empty array on
+ * no match / error.
+ */
+export declare function searchHandle(handle: FileHandle, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Patterns compiled once by `compile_patterns` for any number of searches
+ * with `search_with_set`, independently of the thread-local regex cache.
+ */
+export class PatternSet {
+  /** Number of patterns in the set */
+  get patternCount(): number
+}
+/**
+ * Compile patterns for repeated searches with `search_with_set`. Unlike
+ * `precompile`, the compiled patterns are never evicted; they are kept until
+ * the returned set is garbage collected.
+ *
+ * - `patterns`: Array of regex pattern strings
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Only the
+ *   options that affect compilation, such as `whole_word` or
+ *   `exclude_patterns`, apply.
+ *
+ * Throws if any pattern (or exclude pattern) fails to compile.
+ */
+export declare function compilePatterns(patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): PatternSet
+/**
+ * Search a file with patterns compiled by `compile_patterns`, skipping
+ * compilation entirely. Otherwise behaves like `search_file`.
+ *
+ * - `set`: Patterns returned by `compile_patterns`
+ * - `file_path`: Absolute path to the file to search
+ * - `mode`: `And` requires every pattern to match somewhere in the file,
+ *   `Or` requires at least one pattern to match.
+ * - `options`: Optional extra settings (see `SearchOptions`). Options that
+ *   affect compilation, including `line_terminator`, are taken from
+ *   `compile_patterns` and ignored here.
+ *
+ * Returns a single-element array with match data, or an empty array on
+ * no match / error.
+ */
+export declare function searchWithSet(set: PatternSet, filePath: string, mode: MatchMode, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Count the lines of a file matched by the patterns, without building any
+ * per-line results. Lines are selected the same way as by `search_file`,
+ * including `invert_match` and `within_lines`: with `And`, the count is 0
+ * unless every pattern matches somewhere; with `Or`, it counts lines matched
+ * by any pattern. Lines matched by several patterns are counted once, and
+ * `max_results` does not cap the count.
+ *
+ * - `file_path`: Absolute file path to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: How the patterns are combined (see `search_file`)
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Options that
+ *   only shape per-line output are ignored.
+ *
+ * Returns the number of distinct matching lines, or 0 on no match / error.
+ */
+export declare function countMatchingLines(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): number
+/**
+ * The numbers of the lines of a file matched by the patterns, without
+ * building any per-line results; the cheapest output for minimaps and
+ * heat-maps. Uses the same matching rules as `count_matching_lines`.
+ *
+ * - `file_path`: Absolute file path to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: How the patterns are combined (see `search_file`)
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Options that
+ *   only shape per-line output are ignored.
+ *
+ * Returns the distinct matching line numbers in ascending order, or an empty
+ * array on no match / error.
+ */
+export declare function matchingLines(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<number>
+/**
+ * Check whether a file matches without collecting any results, for gating
+ * logic. Each pattern stops at its first match (outside excluded lines), so
+ * this is much cheaper than a full search.
+ *
+ * - `file_path`: Absolute file path to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: How the patterns are combined (see `search_file`)
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Options that
+ *   only shape per-line output, `invert_match` and `within_lines` are ignored.
+ *
+ * Returns true if the file matches, false on no match / error.
+ */
+export declare function fileMatches(filePath: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): boolean
+/**
+ * Report which patterns occur anywhere in a file, for a quick presence
+ * check across many patterns. Each pattern stops at its first match (outside
+ * excluded lines) and no line numbers are computed. Patterns are checked
+ * independently, without the `And` short-circuit, so this also shows which
+ * patterns made an `And` search of the file come back empty.
+ *
+ * - `file_path`: Absolute file path to search
+ * - `patterns`: Array of regex pattern strings
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Options that
+ *   only shape per-line output, `invert_match` and `within_lines` are ignored.
+ *
+ * Returns one boolean per pattern, in input order, telling whether that
+ * pattern matched. All false on error.
+ */
+export declare function patternPresence(filePath: string, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<boolean>
+/**
+ * Search a file for the lines on which a boolean expression over the
+ * patterns holds, such as `(0 AND 1) OR NOT 2`.
+ *
+ * - `file_path`: Absolute path to the file to search
+ * - `patterns`: Array of regex pattern strings
+ * - `expression`: Combination of patterns, referenced by index or by the
+ *   names in `SearchOptions.pattern_names`, with `AND`, `OR`, `NOT` and
+ *   parentheses. `NOT` binds tightest and `OR` loosest.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Each line is
+ *   matched on its own, so patterns cannot match across lines. Context
+ *   lines (including `context_as_block` and `context_as_lines`),
+ *   `heading_pattern`, `invert_match` (use `NOT` instead), `within_lines`,
+ *   `group_by`, `from_end`, `skip`, `limit`, `SortOrder::MatchOrder` (lines
+ *   stay in line order), `replacement`, `overlapping`,
+ *   `max_matches_per_line`, `collapse_ranges`, `record_mode` and
+ *   `include_stats` are ignored.
+ *
+ * Returns a single-element array whose `patterns` and `lines` cover the
+ * selected lines, or an empty array if no line is selected. Throws if the
+ * expression is invalid, or on the same failures as `search_file_checked`.
+ */
+export declare function searchFileExpr(filePath: string, patterns: Array<string>, expression: string, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Convert a byte offset in a file into a line number without running a
+ * search, for mapping offsets reported by other tools. An offset equal to
+ * the file length refers to the end of the last line.
+ *
+ * - `file_path`: Absolute path to the file
+ * - `offset`: Byte offset from the start of the file
+ * - `options`: Optional extra settings (see `SearchOptions`).
+ *   `line_terminator`, `zero_based` and `read_mode` apply, so the result
+ *   agrees with the line numbers `search_file` reports; the other options
+ *   are ignored.
+ *
+ * Throws if the file cannot be read or `offset` is negative or past the end
+ * of the file.
+ */
+export declare function offsetToLine(filePath: string, offset: number, options?: SearchOptions | undefined | null): number
+/**
+ * Replace every match of the patterns in a file and write the result back.
+ * The patterns are applied in order, each to the output of the previous
+ * one. The file is replaced atomically by writing a temporary file next to
+ * it, flushing it to disk and renaming it over the original, keeping its
+ * permissions. If `file_path` is a symlink, its target is replaced.
+ *
+ * - `file_path`: Absolute path to the file to edit
+ * - `patterns`: Array of regex pattern strings
+ * - `replacement`: Replacement for each match. `$1`, `$name` and `${name}`
+ *   refer to capture groups and `$$` is a literal `$`.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `dry_run`: If true, only count the replacements; the file is not written.
+ * - `options`: Optional extra settings (see `SearchOptions`). The pattern
+ *   options, `exclude_patterns`, `min_line_length`, `guard` and `scope`
+ *   apply; the file is edited as raw bytes, so the content and window options
+ *   are ignored.
+ *
+ * Returns the number of replacements made. The file is left untouched when
+ * there are none. Throws if the file cannot be read or written or a pattern
+ * fails to compile.
+ */
+export declare function replaceInFile(filePath: string, patterns: Array<string>, replacement: string, unicode: boolean, caseInsensitive: boolean, dryRun: boolean, options?: SearchOptions | undefined | null): number
 /**
  * Search multiple files for matches using AND semantics across regex patterns.
  * All patterns must match somewhere in a file for that file's results to be returned.
@@ -43,11 +1363,12 @@ export declare function searchFileAnd(filePath: string, patterns: Array<string>,
  * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
  *   use Unicode classes. If false, raw byte mode for maximum performance.
  * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
  *
  * Returns an array of `FilePatternMatches` for files where all patterns matched,
  * or an empty array on no match / error.
  */
-export declare function searchFilesAnd(filePaths: Array<string>, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean): Array<FilePatternMatches>
+export declare function searchFilesAnd(filePaths: Array<string>, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
 /**
  * Search multiple files for matches using OR semantics across regex patterns.
  * Each pattern is evaluated independently per file. Returns per-pattern
@@ -57,7 +1378,79 @@ export declare function searchFilesAnd(filePaths: Array<string>, patterns: Array
  * - `patterns`: Array of regex pattern strings (each searched independently)
  * - `unicode`: If true, use Unicode character classes. False for performance.
  * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
  *
  * Returns an array of `FilePatternMatches` for files with at least one pattern match.
  */
-export declare function searchFilesOr(filePaths: Array<string>, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean): Array<FilePatternMatches>
+export declare function searchFilesOr(filePaths: Array<string>, patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Search multiple files, combining the patterns according to `mode`.
+ * Files are searched in parallel across a thread pool, and results are
+ * returned in the order of `file_paths`. Only files that match are included
+ * in the output.
+ *
+ * - `file_paths`: Array of absolute file paths to search
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in a file,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`).
+ *
+ * Returns an array of `FilePatternMatches` for matching files, or an empty
+ * array on no match / error.
+ */
+export declare function searchFiles(filePaths: Array<string>, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Recursively search the files under a directory whose relative path matches
+ * a glob, combining the patterns according to `mode`. Files are searched in
+ * parallel like `search_files`.
+ *
+ * - `root`: Directory to search
+ * - `glob`: Glob matched against each file's path relative to `root`, e.g.
+ *   `**/*.rs`. `*` does not cross directory separators; `**` does.
+ * - `patterns`: Array of regex pattern strings
+ * - `mode`: `And` requires every pattern to match somewhere in a file,
+ *   `Or` requires at least one pattern to match.
+ * - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+ *   use Unicode classes. If false, raw byte mode for maximum performance.
+ * - `case_insensitive`: If true, matching is case-insensitive.
+ * - `options`: Optional extra settings (see `SearchOptions`). Set
+ *   `max_file_size`, `min_file_size` or `modified_after` to skip files by
+ *   size or age without opening them.
+ *
+ * Returns an array of `FilePatternMatches` for matching files, sorted by
+ * path, with `file_path` relative to `root`. Returns an empty array on no
+ * match / error.
+ */
+export declare function searchDir(root: string, glob: string, patterns: Array<string>, mode: MatchMode, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<FilePatternMatches>
+/**
+ * Compile `patterns` and store them in the calling thread's cache without
+ * searching anything, so a later search with the same patterns, flags and
+ * compile options (see `cache_info`) skips compilation. Useful for paying the
+ * compile cost during idle time.
+ *
+ * Returns true if every pattern and exclude pattern compiled, false if any
+ * failed or `patterns` is empty.
+ */
+export declare function precompile(patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): boolean
+/**
+ * Check whether each pattern compiles, with exactly the settings a search
+ * with the same arguments would use. Nothing is cached.
+ *
+ * Returns one entry per pattern, in input order: `null` if it compiles,
+ * otherwise the regex error message. Throws if `options.flags` contains an
+ * unknown flag.
+ */
+export declare function validatePatterns(patterns: Array<string>, unicode: boolean, caseInsensitive: boolean, options?: SearchOptions | undefined | null): Array<string | undefined | null>
+/**
+ * Drop the compiled patterns cached on the calling thread, freeing their
+ * memory. The next search compiles its patterns again.
+ */
+export declare function clearCache(): void
+/**
+ * Describe the compiled patterns cached on the calling thread, for checking
+ * whether repeated searches reuse them.
+ */
+export declare function cacheInfo(): CacheInfo
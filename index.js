@@ -310,8 +310,46 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { searchFileAnd, searchFilesAnd, searchFilesOr } = nativeBinding
+const { ReaderSearch, BinaryMode, SortOrder, GroupBy, RecordMode, ColumnUnit, Anchor, Normalization, ReadMode, Encoding, LossyMode, MatchMode, searchFileAnd, searchFile, searchFileJson, searchFileFlat, searchFileChecked, searchFileStream, searchBuffer, searchStrings, FileHandle, openFile, searchHandle, PatternSet, compilePatterns, searchWithSet, countMatchingLines, matchingLines, fileMatches, patternPresence, searchFileExpr, offsetToLine, replaceInFile, searchFilesAnd, searchFilesOr, searchFiles, searchDir, precompile, validatePatterns, clearCache, cacheInfo } = nativeBinding
 
+module.exports.ReaderSearch = ReaderSearch
+module.exports.BinaryMode = BinaryMode
+module.exports.SortOrder = SortOrder
+module.exports.GroupBy = GroupBy
+module.exports.RecordMode = RecordMode
+module.exports.ColumnUnit = ColumnUnit
+module.exports.Anchor = Anchor
+module.exports.Normalization = Normalization
+module.exports.ReadMode = ReadMode
+module.exports.Encoding = Encoding
+module.exports.LossyMode = LossyMode
+module.exports.MatchMode = MatchMode
 module.exports.searchFileAnd = searchFileAnd
+module.exports.searchFile = searchFile
+module.exports.searchFileJson = searchFileJson
+module.exports.searchFileFlat = searchFileFlat
+module.exports.searchFileChecked = searchFileChecked
+module.exports.searchFileStream = searchFileStream
+module.exports.searchBuffer = searchBuffer
+module.exports.searchStrings = searchStrings
+module.exports.FileHandle = FileHandle
+module.exports.openFile = openFile
+module.exports.searchHandle = searchHandle
+module.exports.PatternSet = PatternSet
+module.exports.compilePatterns = compilePatterns
+module.exports.searchWithSet = searchWithSet
+module.exports.countMatchingLines = countMatchingLines
+module.exports.matchingLines = matchingLines
+module.exports.fileMatches = fileMatches
+module.exports.patternPresence = patternPresence
+module.exports.searchFileExpr = searchFileExpr
+module.exports.offsetToLine = offsetToLine
+module.exports.replaceInFile = replaceInFile
 module.exports.searchFilesAnd = searchFilesAnd
 module.exports.searchFilesOr = searchFilesOr
+module.exports.searchFiles = searchFiles
+module.exports.searchDir = searchDir
+module.exports.precompile = precompile
+module.exports.validatePatterns = validatePatterns
+module.exports.clearCache = clearCache
+module.exports.cacheInfo = cacheInfo
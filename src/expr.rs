@@ -0,0 +1,387 @@
+// Copyright (c) 2026 Piet Hein Schouten
+// SPDX-License-Identifier: MIT
+
+use std::time::Instant;
+
+use memchr::memchr_iter;
+
+use crate::{
+    add_line_details, is_excluded_line, limit_total_text, line_ranges, line_terminator, page_lines,
+    prepare_content, shift_line_numbers, skips_invalid_utf8, trim_line_ending, CompiledSearch,
+    DeadlineCheck, ExcludeFilter, FilePatternMatches, LineMatch, LineWindow, MatchRange,
+    PatternMatch, SearchError, SearchOptions, SortOrder,
+};
+
+// ============================================================================
+// Boolean pattern expressions
+// ============================================================================
+
+/// A boolean expression over the patterns of a search, evaluated per line.
+#[derive(Debug)]
+pub(crate) enum Expr {
+    /// True if the pattern with this index matches the line.
+    Pattern(usize),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parse `expression`. Operands are pattern indices (`0`, `1`, ...) or
+    /// names from `names`, combined with `AND`, `OR` and `NOT` (in any case)
+    /// and parentheses. `NOT` binds tightest and `OR` loosest.
+    pub(crate) fn parse(
+        expression: &str,
+        pattern_count: usize,
+        names: &[String],
+    ) -> Result<Self, SearchError> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            pattern_count,
+            names,
+        };
+
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(invalid(format!("unexpected {token}"))),
+        }
+    }
+
+    /// Evaluate the expression given whether each pattern matched.
+    pub(crate) fn eval(&self, matched: &[bool]) -> bool {
+        match self {
+            Expr::Pattern(index) => matched[*index],
+            Expr::Not(expr) => !expr.eval(matched),
+            Expr::And(left, right) => left.eval(matched) && right.eval(matched),
+            Expr::Or(left, right) => left.eval(matched) || right.eval(matched),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// A pattern index or name
+    Operand(&'a str),
+}
+
+impl std::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Operand(operand) => write!(f, "'{operand}'"),
+        }
+    }
+}
+
+fn invalid(message: String) -> SearchError {
+    SearchError::InvalidExpression(message)
+}
+
+/// Split an expression into tokens. Operands are runs of letters, digits and
+/// underscores.
+fn tokenize(expression: &str) -> Result<Vec<Token<'_>>, SearchError> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c if c.is_whitespace() => {}
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+
+                let word = &expression[start..end];
+                tokens.push(match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Operand(word),
+                });
+            }
+            c => return Err(invalid(format!("unexpected '{c}' at offset {start}"))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of an expression.
+struct Parser<'a> {
+    tokens: &'a [Token<'a>],
+    pos: usize,
+    pattern_count: usize,
+    names: &'a [String],
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token<'_>> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, expected: Token<'_>) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, SearchError> {
+        let mut expr = self.and()?;
+        while self.accept(Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, SearchError> {
+        let mut expr = self.not()?;
+        while self.accept(Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, SearchError> {
+        if self.accept(Token::Not) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.operand()
+    }
+
+    fn operand(&mut self) -> Result<Expr, SearchError> {
+        let pattern_count = self.pattern_count;
+        let names = self.names;
+
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if !self.accept(Token::Close) {
+                    return Err(invalid("missing ')'".to_string()));
+                }
+                Ok(expr)
+            }
+            Some(Token::Operand(operand)) => {
+                let index = match operand.parse::<usize>() {
+                    Ok(index) => Some(index).filter(|&i| i < pattern_count),
+                    Err(_) => names.iter().position(|name| name == operand),
+                };
+                index
+                    .map(Expr::Pattern)
+                    .ok_or_else(|| invalid(format!("unknown pattern '{operand}'")))
+            }
+            Some(token) => Err(invalid(format!("unexpected {token}"))),
+            None => Err(invalid("unexpected end of expression".to_string())),
+        }
+    }
+}
+
+// ============================================================================
+// Search core
+// ============================================================================
+
+/// Select the lines of `bytes` on which `expr` holds.
+pub(crate) fn search_bytes_expr(
+    file_path: &str,
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    expr: &Expr,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Option<FilePatternMatches> {
+    let head_cut = options
+        .head_bytes
+        .is_some_and(|head_bytes| bytes.len() > head_bytes as usize);
+    let content = prepare_content(bytes, compiled, options)?;
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let mut deadline = DeadlineCheck::new(deadline);
+
+    let terminator = line_terminator(options);
+    let min_line_length = options.min_line_length.unwrap_or(0);
+    let skip_invalid = skips_invalid_utf8(options);
+    let max_lines = options.max_results.unwrap_or(u32::MAX);
+    let include_lines = options.include_lines.unwrap_or(false);
+    let dedup_positions = options.dedup_positions.unwrap_or(true);
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    // Frequency and line numbers for each pattern, in input order
+    let mut pattern_stats = vec![(0u32, Vec::new()); compiled.regexes.len()];
+    let mut matched = vec![false; compiled.regexes.len()];
+    let mut lines = Vec::new();
+    let mut selected_lines = 0;
+    let mut total_matches = 0;
+    let mut limited = false;
+
+    for ((start, end), line_number) in line_ranges(bytes, terminator).zip(1u32..) {
+        if deadline.expired() {
+            break;
+        }
+
+        let line = &bytes[start..end];
+        let text = trim_line_ending(line, terminator);
+        if is_excluded_line(
+            text,
+            &compiled.exclude_regexes,
+            min_line_length,
+            skip_invalid,
+        ) {
+            continue;
+        }
+
+        // Most lines are not selected, so decide with `is_match` before
+        // collecting any match positions.
+        let line_range = start..start + text.len();
+        for (is_match, regex) in matched.iter_mut().zip(&compiled.regexes) {
+            *is_match = exclude_filter.line_matches(regex, bytes, line_range.clone());
+        }
+        if !expr.eval(&matched) {
+            continue;
+        }
+        if selected_lines >= max_lines {
+            limited = true;
+            break;
+        }
+        selected_lines += 1;
+
+        let found: Vec<Vec<(usize, usize)>> = compiled
+            .regexes
+            .iter()
+            .map(|regex| {
+                regex
+                    .find_iter(text)
+                    .filter(|m| !exclude_filter.rejects(bytes, start + m.start()..start + m.end()))
+                    .map(|m| (m.start(), m.end()))
+                    .collect()
+            })
+            .collect();
+
+        let mut ranges = Vec::new();
+        let mut matched_patterns = Vec::new();
+        for (idx, pattern_ranges) in found.into_iter().enumerate() {
+            if pattern_ranges.is_empty() {
+                continue;
+            }
+            let stats = &mut pattern_stats[idx];
+            stats.0 += pattern_ranges.len() as u32;
+            stats.1.push(line_number);
+            matched_patterns.push(idx as u32);
+            ranges.extend(pattern_ranges);
+        }
+
+        ranges.sort_unstable();
+        if dedup_positions {
+            ranges.dedup();
+            let mut starts: Vec<usize> = ranges.iter().map(|&(start, _)| start).collect();
+            starts.dedup();
+            total_matches += starts.len() as u32;
+        } else {
+            total_matches += ranges.len() as u32;
+        }
+
+        if !include_lines {
+            continue;
+        }
+
+        let column = ranges.first().map_or(0, |&(start, _)| start as u32);
+        let mut line_match = LineMatch::new(
+            line_number,
+            (start + column as usize) as i64,
+            column,
+            ranges
+                .into_iter()
+                .map(|(start, end)| MatchRange {
+                    start: start as u32,
+                    end: end as u32,
+                    end_line: None,
+                    end_column: None,
+                    bytes: None,
+                    captures: None,
+                })
+                .collect(),
+            matched_patterns,
+        );
+        add_line_details(&mut line_match, line, text, &compiled.regexes, options);
+        lines.push(line_match);
+    }
+
+    if selected_lines == 0 {
+        return None;
+    }
+    page_lines(&mut lines, options);
+
+    let mut patterns: Vec<PatternMatch> = pattern_stats
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (frequency, _))| *frequency > 0)
+        .map(|(idx, (frequency, line_numbers))| PatternMatch {
+            pattern_index: idx as u32,
+            frequency,
+            line_numbers,
+        })
+        .collect();
+    window.offset_results(&mut patterns, Some(&mut lines));
+
+    if options.zero_based.unwrap_or(false) {
+        for pattern in &mut patterns {
+            for line_number in &mut pattern.line_numbers {
+                *line_number -= 1;
+            }
+        }
+        for line in &mut lines {
+            shift_line_numbers(line, -1);
+        }
+    }
+
+    if matches!(options.sort, Some(SortOrder::Descending)) {
+        for pattern in &mut patterns {
+            pattern.line_numbers.reverse();
+        }
+        lines.reverse();
+    }
+    let text_truncated = limit_total_text(&mut lines, options);
+
+    let total_lines = if options.include_total_lines.unwrap_or(true) {
+        memchr_iter(terminator, &content[window.region]).count() as u32 + 1
+    } else {
+        0
+    };
+
+    Some(FilePatternMatches {
+        file_path: file_path.to_string(),
+        total_lines,
+        total_matches,
+        matching_lines: selected_lines,
+        patterns,
+        lines: include_lines.then_some(lines),
+        line_ranges: None,
+        partial: deadline.expired,
+        truncated: limited || head_cut || deadline.expired,
+        text_truncated,
+        error: None,
+        stats: None,
+        input_index: None,
+    })
+}
@@ -1,24 +1,307 @@
 // Copyright (c) 2026 Piet Hein Schouten
 // SPDX-License-Identifier: MIT
 
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use memchr::memchr_iter;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use memchr::{memchr, memchr_iter, memrchr};
+use napi::bindgen_prelude::{Buffer, Float64Array, Function, Uint32Array};
 use napi_derive::napi;
+use rayon::prelude::*;
 use regex::bytes::Regex;
+use regex_syntax::ast::{self, Ast};
+use regex_syntax::hir::print::Printer;
+use regex_syntax::hir::translate::TranslatorBuilder;
+use regex_syntax::hir::{
+    self, Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Hir, HirKind,
+};
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+mod expr;
+mod reader;
+mod walk;
 
 // ============================================================================
 // Types
 // ============================================================================
 
 struct CachedSearch {
-    /// Cache key: the pattern strings joined with a \0 delimiter,
-    /// plus \0 + "1" or "0" for the unicode flag.
+    /// Cache key built by `build_cache_key`.
     cache_key: String,
+    compiled: Arc<CompiledSearch>,
+}
+
+/// Compiled regexes for one set of patterns and options.
+struct CompiledSearch {
+    /// One regex per input pattern, in input order.
     regexes: Vec<Regex>,
+    /// Matches on lines matching any of these are discarded.
+    exclude_regexes: Vec<Regex>,
+    /// Matches followed by a match of this are discarded.
+    guard: Option<Regex>,
+    /// Matches starting outside the matches of this are discarded.
+    scope: Option<Regex>,
+    /// Lines matching this are reported as the heading of the lines below.
+    heading: Option<Regex>,
+    /// Table the content is mapped through before matching, in byte mode.
+    byte_map: Option<Vec<u8>>,
+}
+
+/// Reasons a search can fail. The lenient search functions treat all of these
+/// as "no results"; the `_checked` variants turn them into JS exceptions.
+#[derive(Debug)]
+enum SearchError {
+    /// The file does not exist.
+    FileNotFound(String),
+    /// Opening, reading or memory-mapping the file failed.
+    Io {
+        file_path: String,
+        error: std::io::Error,
+    },
+    /// A search pattern failed to compile.
+    InvalidPattern { index: usize, error: regex::Error },
+    /// An exclude pattern failed to compile.
+    InvalidExcludePattern { index: usize, error: regex::Error },
+    /// The guard pattern failed to compile.
+    InvalidGuardPattern(regex::Error),
+    /// The scope pattern failed to compile.
+    InvalidScopePattern(regex::Error),
+    /// The heading pattern failed to compile.
+    InvalidHeadingPattern(regex::Error),
+    /// A directory search glob failed to parse.
+    InvalidGlob(globset::Error),
+    /// A boolean pattern expression failed to parse.
+    InvalidExpression(String),
+    /// A character in the `flags` option is not a known flag.
+    InvalidFlag(char),
+    /// The `byte_map` option does not have 256 entries; holds its length.
+    InvalidByteMap(usize),
+    /// `start_byte`, `byte_length` or an offset is negative; holds the
+    /// option name and its value.
+    NegativeByteRange(&'static str, i64),
+    /// `base_line` and `start_line` together number the first selected line
+    /// past `u32::MAX`; holds that line number.
+    LineNumberOverflow(u64),
+    /// A byte offset is past the end of the file.
+    OffsetOutOfRange {
+        file_path: String,
+        offset: i64,
+        length: usize,
+    },
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::FileNotFound(file_path) => write!(f, "File not found: {file_path}"),
+            SearchError::Io { file_path, error } => {
+                write!(f, "Failed to read {file_path}: {error}")
+            }
+            SearchError::InvalidPattern { index, error } => {
+                write!(f, "Invalid pattern at index {index}: {error}")
+            }
+            SearchError::InvalidExcludePattern { index, error } => {
+                write!(f, "Invalid exclude pattern at index {index}: {error}")
+            }
+            SearchError::InvalidGuardPattern(error) => write!(f, "Invalid guard pattern: {error}"),
+            SearchError::InvalidScopePattern(error) => write!(f, "Invalid scope pattern: {error}"),
+            SearchError::InvalidHeadingPattern(error) => {
+                write!(f, "Invalid heading pattern: {error}")
+            }
+            SearchError::InvalidGlob(error) => write!(f, "Invalid glob: {error}"),
+            SearchError::InvalidExpression(message) => {
+                write!(f, "Invalid expression: {message}")
+            }
+            SearchError::InvalidFlag(flag) => write!(f, "Invalid flag: '{flag}'"),
+            SearchError::InvalidByteMap(length) => {
+                write!(f, "Invalid byte map: expected 256 entries, got {length}")
+            }
+            SearchError::NegativeByteRange(option, value) => {
+                write!(f, "Invalid {option}: {value} is negative")
+            }
+            SearchError::LineNumberOverflow(line) => write!(
+                f,
+                "Invalid baseLine/startLine: first line number {line} exceeds {}",
+                u32::MAX
+            ),
+            SearchError::OffsetOutOfRange {
+                file_path,
+                offset,
+                length,
+            } => write!(
+                f,
+                "Offset {offset} is out of range for {file_path} ({length} bytes)"
+            ),
+        }
+    }
+}
+
+impl From<SearchError> for napi::Error {
+    fn from(error: SearchError) -> Self {
+        let status = match error {
+            SearchError::InvalidPattern { .. }
+            | SearchError::InvalidExcludePattern { .. }
+            | SearchError::InvalidGuardPattern(_)
+            | SearchError::InvalidScopePattern(_)
+            | SearchError::InvalidHeadingPattern(_)
+            | SearchError::InvalidGlob(_)
+            | SearchError::InvalidExpression(_)
+            | SearchError::InvalidFlag(_)
+            | SearchError::InvalidByteMap(_)
+            | SearchError::NegativeByteRange(..)
+            | SearchError::LineNumberOverflow(_)
+            | SearchError::OffsetOutOfRange { .. } => napi::Status::InvalidArg,
+            SearchError::FileNotFound(_) | SearchError::Io { .. } => napi::Status::GenericFailure,
+        };
+        napi::Error::new(status, error.to_string())
+    }
+}
+
+/// How content that looks binary (contains a NUL byte near the start) is
+/// handled.
+#[napi]
+pub enum BinaryMode {
+    /// Binary content produces no results.
+    Skip,
+    /// Binary content is searched like any other content.
+    Search,
+    /// Binary content is searched after replacing invalid UTF-8 sequences
+    /// with U+FFFD. Offsets then refer to the replaced content.
+    TextReplace,
+}
+
+/// Order of the per-line results within a file.
+#[napi]
+pub enum SortOrder {
+    /// By ascending line number.
+    Ascending,
+    /// By descending line number.
+    Descending,
+    /// In the order the matches were found: all lines matched by the first
+    /// pattern, then the new lines matched by the second, and so on.
+    MatchOrder,
+}
+
+/// Whether `lines` has one entry per matching line or per match.
+#[napi]
+pub enum GroupBy {
+    /// One entry per line, listing all of its matches.
+    Line,
+    /// One entry per match, so a line with several matches appears several
+    /// times.
+    Match,
+}
+
+/// How content is split into the records returned with matching lines.
+#[napi]
+pub enum RecordMode {
+    /// Each line is its own record.
+    Line,
+    /// Records are paragraphs: runs of non-empty lines separated by empty
+    /// lines.
+    Paragraph,
+}
+
+/// Unit of the columns reported in `LineMatch` and `MatchRange`.
+#[napi]
+pub enum ColumnUnit {
+    /// Bytes of the (transcoded) UTF-8 content.
+    Byte,
+    /// Unicode scalar values, as counted by `[...str].length` in JavaScript.
+    Char,
+    /// UTF-16 code units, matching JavaScript string indices for
+    /// `.length` and `.slice`.
+    Utf16,
+}
+
+/// Where one pattern must match within a line, see `SearchOptions.anchors`.
+#[napi]
+#[derive(Debug)]
+pub enum Anchor {
+    /// Anywhere in the line.
+    None,
+    /// At the start of a line.
+    LineStart,
+    /// At the end of a line.
+    LineEnd,
+    /// The entire line.
+    FullLine,
+}
+
+/// Unicode normalization form applied before matching.
+#[napi]
+#[derive(Debug)]
+pub enum Normalization {
+    /// Canonical composition, e.g. `é` as one code point.
+    Nfc,
+    /// Canonical decomposition, e.g. `é` as `e` followed by a combining
+    /// accent.
+    Nfd,
+}
+
+/// How file content is loaded.
+#[napi]
+pub enum ReadMode {
+    /// Memory-map the file. Fails on files that cannot be mapped.
+    Mmap,
+    /// Read the whole file into memory.
+    Read,
+    /// Memory-map non-empty regular files and read everything else, or when
+    /// mapping fails.
+    Auto,
+}
+
+/// Text encoding of the content being searched.
+#[napi]
+pub enum Encoding {
+    /// UTF-8 (or any ASCII-compatible encoding), searched in place.
+    Utf8,
+    /// UTF-16 little-endian, transcoded to UTF-8 before searching.
+    Utf16Le,
+    /// UTF-16 big-endian, transcoded to UTF-8 before searching.
+    Utf16Be,
+    /// ISO-8859-1, transcoded to UTF-8 before searching.
+    Latin1,
+}
+
+/// How lines that are not valid UTF-8 are returned.
+#[napi]
+pub enum LossyMode {
+    /// Invalid sequences are replaced with U+FFFD.
+    Lossy,
+    /// Such lines are left out of the results, as if excluded. Context lines
+    /// are left empty and `record_text` is left out.
+    Skip,
+    /// `text` holds the raw bytes base64-encoded, with `text_base64` set;
+    /// likewise for context lines and `record_text`.
+    Base64,
+}
+
+/// How per-pattern results are combined into a file-level match.
+#[napi]
+pub enum MatchMode {
+    /// Every pattern must match somewhere in the file. Set
+    /// `SearchOptions.within_lines` to 0 to require every pattern on the same
+    /// line instead.
+    And,
+    /// At least one pattern must match somewhere in the file.
+    Or,
 }
 
 #[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PatternMatch {
     /// Index into the input patterns array (0-based)
     pub pattern_index: u32,
@@ -29,25 +312,795 @@ pub struct PatternMatch {
 }
 
 #[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FilePatternMatches {
     /// Absolute file path
     pub file_path: String,
-    /// Total number of lines in the file
+    /// Total number of lines in the file, for example to compute match
+    /// density together with `matching_lines`. 0 when
+    /// `SearchOptions.include_total_lines` is false.
     pub total_lines: u32,
+    /// Number of matches across all patterns. Matches of different patterns
+    /// at the same position count once. For inverted searches, the number of
+    /// selected lines.
+    pub total_matches: u32,
+    /// Number of distinct lines in the patterns' `line_numbers`
+    pub matching_lines: u32,
     /// Per-pattern match data. Only patterns with >= 1 match are included.
     pub patterns: Vec<PatternMatch>,
+    /// Per-line match details across all included patterns, sorted by line.
+    /// Only present when `SearchOptions.include_lines` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<LineMatch>>,
+    /// Runs of consecutive matching lines, in the order of `lines`. Only
+    /// present when `SearchOptions.collapse_ranges` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_ranges: Option<Vec<LineRange>>,
+    /// True if `SearchOptions.deadline_ms` passed while this file was being
+    /// searched, so the results only cover what was found until then.
+    pub partial: bool,
+    /// True if a limit left matches out of the results: `max_results`,
+    /// `max_matches_per_line`, `head_bytes` cutting the file, or
+    /// `deadline_ms` (see `partial`). Lets callers tell "the first 1000 of
+    /// many" from complete results.
+    pub truncated: bool,
+    /// Whether `SearchOptions.max_total_text_bytes` ran out, leaving the
+    /// `text` of the remaining lines empty and dropping their other strings.
+    /// Only present when that budget is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_truncated: Option<bool>,
+    /// Why the file could not be searched, such as a missing file or a
+    /// permission error. Only set on the entries that
+    /// `SearchOptions.report_errors` adds for failed files, which have no
+    /// matches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Diagnostics about the search. Only present when
+    /// `SearchOptions.include_stats` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<SearchStats>,
+    /// Index of the searched string in the `inputs` of `search_strings`. Only
+    /// present on its results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_index: Option<u32>,
+}
+
+impl FilePatternMatches {
+    /// Entry for a file that could not be searched because of `error`.
+    fn failed(file_path: &str, error: &SearchError) -> Self {
+        FilePatternMatches {
+            file_path: file_path.to_string(),
+            total_lines: 0,
+            total_matches: 0,
+            matching_lines: 0,
+            patterns: Vec::new(),
+            lines: None,
+            line_ranges: None,
+            partial: false,
+            truncated: false,
+            text_truncated: None,
+            error: Some(error.to_string()),
+            stats: None,
+            input_index: None,
+        }
+    }
+}
+
+/// Timing and size diagnostics for one searched file. These only describe
+/// the search and never affect its results.
+#[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchStats {
+    /// Number of bytes matched against, after decompression and the line
+    /// window are applied
+    pub bytes_scanned: u32,
+    /// Time spent matching the patterns, in microseconds
+    pub match_time_us: u32,
+    /// Time spent compiling the patterns for this call, in microseconds. 0
+    /// when the compiled patterns came from the regex cache.
+    pub compile_time_us: u32,
+}
+
+/// A run of consecutive matching lines.
+#[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineRange {
+    /// First line of the run
+    pub start_line: u32,
+    /// Last line of the run (inclusive)
+    pub end_line: u32,
+    /// Text of the first line of the run. Only present when
+    /// `SearchOptions.include_text` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Text of every line of the run. Only present when
+    /// `SearchOptions.include_range_lines` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<String>>,
+}
+
+/// Position of one match. Columns are in bytes unless
+/// `SearchOptions.column_unit` or `SearchOptions.tab_width` is set.
+#[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchRange {
+    /// 0-based start column of the match, in bytes from the start of the line
+    pub start: u32,
+    /// End column of the match (exclusive), in bytes from the start of the
+    /// line. May extend past the line for matches that span a line ending.
+    pub end: u32,
+    /// Line number of the line the match ends on, numbered like
+    /// `LineMatch.line_number`. Only present for matches that span a line
+    /// ending; a match whose last byte is the line terminator ends on its
+    /// starting line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    /// End column of the match (exclusive) in bytes from the start of
+    /// `end_line`. Only present when `end_line` is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
+    /// The exact matched bytes, base64 encoded (`Buffer.from(bytes,
+    /// 'base64')`), for binary or mixed content where `text` is decoded
+    /// lossily. Only present when `SearchOptions.include_match_bytes` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<String>,
+    /// Text of each named capture group that took part in the match, by group
+    /// name, decoded as UTF-8 lossily. Only present when
+    /// `SearchOptions.captures` is true and the pattern that found the match
+    /// has named groups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captures: Option<BTreeMap<String, String>>,
+}
+
+#[napi(object)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMatch {
+    /// 1-based line number
+    pub line_number: u32,
+    /// Byte offset from the start of the file to the first match on this line
+    pub byte_offset: i64,
+    /// 0-based column of the first match on this line, in bytes from the
+    /// start of the line (or in `SearchOptions.column_unit`)
+    pub column: u32,
+    /// Every match on this line from all patterns, sorted by start column.
+    /// Empty for inverted searches.
+    pub matches: Vec<MatchRange>,
+    /// Indices into the input patterns array of the patterns that matched
+    /// this line, sorted. For inverted searches, the patterns that matched
+    /// the selected line (only possible with `MatchMode.And`).
+    pub matched_patterns: Vec<u32>,
+    /// Text of the line without its line ending, decoded as UTF-8 according
+    /// to `SearchOptions.lossy_mode`. Only present when
+    /// `SearchOptions.include_text` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Column within the line where `text` starts. Only present when
+    /// `SearchOptions.max_line_length` or `SearchOptions.snippet_radius` is
+    /// set; non-zero when `text` does not start at the start of the line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_offset: Option<u32>,
+    /// Whether `text` was cut at either end to `SearchOptions.max_line_length`
+    /// or `SearchOptions.snippet_radius`. Only present when one of them is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// Number of leading space and tab bytes of the line, for aligning
+    /// snippets. Counted on the whole line, even when `text` is cut. Only
+    /// present when `SearchOptions.include_indent` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indent: Option<u32>,
+    /// Text of the line after replacing every match of the first pattern
+    /// with `SearchOptions.replacement`. Only present on matching lines when
+    /// a replacement is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaced_text: Option<String>,
+    /// Line number of the first line of the record containing this line.
+    /// Only present when `SearchOptions.record_mode` is `Paragraph`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_line: Option<u32>,
+    /// Text of the record containing this line, limited to
+    /// `SearchOptions.max_line_length` bytes around the first match. Only
+    /// present on the first returned line of each record when
+    /// `SearchOptions.record_mode` is `Paragraph`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_text: Option<String>,
+    /// Up to `SearchOptions.context_before` lines preceding this line. Lines
+    /// that are themselves matches or already part of the previous match's
+    /// context are not repeated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_before: Option<Vec<String>>,
+    /// Up to `SearchOptions.context_after` lines following this line, stopping
+    /// before the next matching line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_after: Option<Vec<String>>,
+    /// The line with its context lines joined by `\n`, in place of
+    /// `context_before` and `context_after`. Only present when
+    /// `SearchOptions.context_as_block` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    /// 0-based index of this line among the lines of `block`. Only present
+    /// with `block`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_line: Option<u32>,
+    /// False for a context line returned as its own entry, true for a
+    /// matching line. Only present when `SearchOptions.context_as_lines` is
+    /// true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_match: Option<bool>,
+    /// Text of the nearest line above this one matching
+    /// `SearchOptions.heading_pattern`. Only present when a heading pattern
+    /// is given and such a line exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    /// True if `text` holds the line's raw bytes base64-encoded because they
+    /// are not valid UTF-8. Only present with `LossyMode.Base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_base64: Option<bool>,
+    /// True if every line of `context_before`, `context_after` or `block`
+    /// holds its raw bytes base64-encoded because at least one of them is not
+    /// valid UTF-8. Only present with `LossyMode.Base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_base64: Option<bool>,
+    /// True if `record_text` holds the record's raw bytes base64-encoded
+    /// because they are not valid UTF-8. Only present with
+    /// `LossyMode.Base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_text_base64: Option<bool>,
+}
+
+impl LineMatch {
+    /// A line with these matches and every optional field unset.
+    fn new(
+        line_number: u32,
+        byte_offset: i64,
+        column: u32,
+        matches: Vec<MatchRange>,
+        matched_patterns: Vec<u32>,
+    ) -> Self {
+        LineMatch {
+            line_number,
+            byte_offset,
+            column,
+            matches,
+            matched_patterns,
+            text: None,
+            text_offset: None,
+            truncated: None,
+            indent: None,
+            replaced_text: None,
+            record_line: None,
+            record_text: None,
+            context_before: None,
+            context_after: None,
+            block: None,
+            block_line: None,
+            is_match: None,
+            heading: None,
+            text_base64: None,
+            context_base64: None,
+            record_text_base64: None,
+        }
+    }
+
+    /// Byte offset of the start of the line.
+    fn line_start(&self) -> usize {
+        self.byte_offset as usize - self.column as usize
+    }
+}
+
+/// The lines of a `search_file_flat` result as parallel typed arrays, one
+/// entry per matching line, which are much cheaper to transfer to a worker
+/// than an array of objects.
+#[napi(object)]
+pub struct FlatMatches {
+    /// `LineMatch.line_number` of each line
+    pub lines: Uint32Array,
+    /// `LineMatch.byte_offset` of each line
+    pub offsets: Float64Array,
+    /// `LineMatch.column` of each line
+    pub columns: Uint32Array,
+    /// `LineMatch.text` of every line concatenated, as UTF-8. Only present
+    /// when `SearchOptions.include_text` is true.
+    pub text: Option<Buffer>,
+    /// Start of each line's text in `text`, plus the end of the last one, so
+    /// line `i` is `text[text_offsets[i]..text_offsets[i + 1]]`. Only present
+    /// with `text`.
+    pub text_offsets: Option<Uint32Array>,
+}
+
+#[napi(object)]
+pub struct CacheInfo {
+    /// Whether any compiled pattern set is cached
+    pub has_entry: bool,
+    /// Key of the most recently used pattern set: the patterns, each tagged
+    /// and length-prefixed, then a NUL character and the compile settings
+    pub cache_key: Option<String>,
+    /// Keys of all cached pattern sets, most recently used first
+    pub cache_keys: Vec<String>,
+}
+
+/// Optional search settings shared by all search functions. Every field may be
+/// omitted, in which case the documented default is used.
+#[napi(object)]
+#[derive(Default, Clone)]
+pub struct SearchOptions {
+    /// If true, each pattern is matched case-insensitively when it contains no
+    /// uppercase characters, and case-sensitively otherwise (like ripgrep's
+    /// `--smart-case`). Takes precedence over `case_insensitive`.
+    /// Default: false.
+    pub smart_case: Option<bool>,
+    /// Regex patterns whose matching lines are excluded. Matches on a line
+    /// matching any of these are discarded before the AND/OR check, so a
+    /// pattern that only matches on excluded lines counts as not matching.
+    /// Exclusion is scoped to the line: other lines of the file still match.
+    /// Compiled with the same flags as `patterns`. Default: none.
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Lines shorter than this many bytes, without their line ending, are
+    /// excluded like lines matching `exclude_patterns`, to skip noise on very
+    /// short lines. Default: 0.
+    pub min_line_length: Option<u32>,
+    /// Regex tested right after each match, as a substitute for negative
+    /// lookahead ("X not followed by Y"): a match is discarded when the guard
+    /// matches within `guard_window` bytes after its end. `^` anchors to the
+    /// match end. Compiled with the same flags as `patterns`. Default: none.
+    pub guard: Option<String>,
+    /// Number of bytes after each match that `guard` is tested against.
+    /// Default: 64.
+    pub guard_window: Option<u32>,
+    /// Regex whose matches are the only regions searched, such as comments
+    /// (`/\*[\s\S]*?\*/`) or strings: a match is kept only if it starts
+    /// inside a match of `scope`. The scope is matched once over the whole
+    /// content. Compiled with the same flags as `patterns`. Default: none.
+    pub scope: Option<String>,
+    /// Field delimiter byte for delimited data, such as 44 (`,`) for CSV or 9
+    /// (`\t`) for TSV: a match is kept only if it starts and ends on a field
+    /// boundary, that is next to this byte or at the start or end of a line.
+    /// Default: none.
+    pub field_delimiter: Option<u8>,
+    /// If true, patterns only match whole words, as if each were wrapped in
+    /// `\b(?:...)\b`. Word boundaries follow the `unicode` flag. Default: false.
+    pub whole_word: Option<bool>,
+    /// If true, each pattern must match an entire line, as if wrapped in
+    /// `(?m:^(?:...)$)`. Combines with `literal` and `whole_word`; lines ending
+    /// in CRLF match without their `\r`. Default: false.
+    pub full_line_match: Option<bool>,
+    /// If true, each pattern must match at the start of a line, as if wrapped
+    /// in `(?m:^(?:...))`, for fast "lines starting with" filters. Unlike
+    /// `full_line_match` the match may end anywhere on the line. Default: false.
+    pub line_prefix: Option<bool>,
+    /// Names for the patterns, in pattern order, that `search_file_expr`
+    /// expressions can use instead of pattern indices. Default: none.
+    pub pattern_names: Option<Vec<String>>,
+    /// Where each pattern must match within a line, in pattern order, for
+    /// anchoring some patterns but not others. Patterns without an entry are
+    /// not anchored. Applied on top of `full_line_match` and `line_prefix`.
+    /// Default: none.
+    pub anchors: Option<Vec<Anchor>>,
+    /// If true, `^` and `$` match at the start and end of every line; if
+    /// false, only at the start and end of the content (or of each line for
+    /// `ReaderSearch`, which matches lines on their own). `.` never matches
+    /// the line terminator either way. Results are still reported per line,
+    /// on the line where each match starts. `full_line_match` always anchors
+    /// to lines. Default: true.
+    pub multi_line: Option<bool>,
+    /// If true, `.` also matches the line terminator, so patterns such as
+    /// `START.*END` can span lines. A match that spans lines is reported once,
+    /// on the line where it starts: only that line counts towards
+    /// `line_numbers` and `matching_lines`, and its `MatchRange.end` extends
+    /// past the line. Default: false.
+    pub dot_all: Option<bool>,
+    /// If true, patterns are plain strings rather than regexes: all regex
+    /// metacharacters are escaped, so `a.b` only matches the text `a.b`.
+    /// Default: false.
+    pub literal: Option<bool>,
+    /// Ripgrep-style short flags as one string, such as `"wiF"`, for terse
+    /// call sites: `i` case-insensitive, `S` smart case, `w` whole word, `x`
+    /// full line match, `F` literal and `s` dot-all. Each turns its setting on
+    /// in addition to the other arguments and options, so no combination
+    /// conflicts: `S` wins over `i` and `x` over `line_prefix`, as their
+    /// options do. Unknown characters are an error. Default: none.
+    pub flags: Option<String>,
+    /// Overrides the `unicode` argument for `.`, `\w`, `\d`, `\s`, `\b` and
+    /// character classes. Default: the `unicode` argument.
+    pub unicode_dot: Option<bool>,
+    /// Overrides the `unicode` argument for case folding: Unicode simple case
+    /// folding if true, ASCII letters only if false. When this differs from
+    /// `unicode_dot`, case-insensitive patterns are rewritten with explicit
+    /// folded classes, which makes them slower to compile. Default: the
+    /// `unicode` argument.
+    pub unicode_case: Option<bool>,
+    /// If true, each result also carries `lines` with per-line match details.
+    /// Default: false.
+    pub include_lines: Option<bool>,
+    /// Maximum number of matching lines reported per file. Each pattern's
+    /// `line_numbers` and the `lines` array keep at most this many lines, the
+    /// earliest ones, and `frequency` only counts matches on the kept lines.
+    /// Scanning for a pattern stops once its limit is reached. Default: no limit.
+    pub max_results: Option<u32>,
+    /// If true, `max_results` keeps the last matching lines instead of the
+    /// earliest, for tailing logs. The content is still scanned from the
+    /// start, since line numbers are counted from there, and scanning no
+    /// longer stops at the limit. To scan only a tail window, set
+    /// `start_byte` to shortly before the end instead; line numbers then
+    /// count from the window. Ignored by `search_file_expr`,
+    /// `search_file_stream` and `ReaderSearch`. Default: false.
+    pub from_end: Option<bool>,
+    /// Number of entries of `lines` to leave out, in the order they are
+    /// returned, for paging through large results. Only `lines` is paged;
+    /// `patterns` and the counts still describe every matching line, e.g. to
+    /// show "page X of Y". Default: 0.
+    pub skip: Option<u32>,
+    /// Maximum number of entries of `lines` returned after `skip`. Unlike
+    /// `max_results`, this does not limit `patterns` or the counts.
+    /// Default: no limit.
+    pub limit: Option<u32>,
+    /// If true, each entry in `lines` carries the line's `text`. Requires
+    /// `include_lines`. Default: false.
+    pub include_text: Option<bool>,
+    /// If true, each entry in `lines` with `text` also carries the line's
+    /// `indent`. Requires `include_text`. Default: false.
+    pub include_indent: Option<bool>,
+    /// How lines that are not valid UTF-8 are returned: with replacement
+    /// characters, left out of the results, or base64-encoded. Applies to
+    /// `text`, the context lines, `block` and `record_text`; headings and
+    /// other text are always decoded lossily. Default: `Lossy`.
+    pub lossy_mode: Option<LossyMode>,
+    /// If false, `total_lines` is reported as 0, saving the extra pass over
+    /// the content that counts its lines. Default: true.
+    pub include_total_lines: Option<bool>,
+    /// If true, `search_files` and `search_dir` only report how much each
+    /// file matches, for ranking files before drilling in: `matching_lines`,
+    /// `total_matches` and `total_lines`, plus each pattern's `frequency`
+    /// with empty `line_numbers`. No `lines` or `line_ranges` are built,
+    /// regardless of the options requesting them, and the matches are only
+    /// counted, unless `invert_match`, `within_lines` with `And` or
+    /// `from_end` with `max_results` need their line numbers. Default: false.
+    pub summary_only: Option<bool>,
+    /// If true, each entry in `matches` carries the exact matched `bytes`,
+    /// base64 encoded. Requires `include_lines`. Default: false.
+    pub include_match_bytes: Option<bool>,
+    /// If true, each entry in `matches` carries the text of the named capture
+    /// groups of the pattern that found it, e.g. to extract a timestamp and
+    /// level from a log line. Requires `include_lines`. Default: false.
+    pub captures: Option<bool>,
+    /// If true, each result also carries `line_ranges`: the matching lines
+    /// merged into runs of consecutive lines, for folding large matching
+    /// blocks. Runs carry the text of their first line with `include_text`.
+    /// Does not require `include_lines`. Default: false.
+    pub collapse_ranges: Option<bool>,
+    /// If true, each of the `line_ranges` carries the text of all of its
+    /// lines. Default: false.
+    pub include_range_lines: Option<bool>,
+    /// If true, each result carries `stats` with the number of bytes scanned
+    /// and the time spent compiling and matching, for diagnosing
+    /// performance. Default: false.
+    pub include_stats: Option<bool>,
+    /// If false, matches of different patterns at the same position are not
+    /// merged: each is listed in `matches` and counted in `total_matches`.
+    /// Lines are still reported once. Merging sorts every match position, so
+    /// turning it off saves that work when patterns cannot overlap, at the
+    /// cost of larger results when they do. Default: true.
+    pub dedup_positions: Option<bool>,
+    /// With `Paragraph`, each entry in `lines` also carries the paragraph
+    /// containing it (`record_line` and `record_text`), for searching blocks
+    /// such as email headers. Matching still works on lines. Requires
+    /// `include_lines`; ignored by `search_file_stream` and `ReaderSearch`.
+    /// Default: `Line`.
+    pub record_mode: Option<RecordMode>,
+    /// With `Match`, `lines` has one entry per match instead of per line, each
+    /// with its own `column`, `byte_offset`, single-element `matches` and the
+    /// patterns that found that match. `max_results` still counts lines, and
+    /// context lines go with the first and last entry of a line. Ignored for
+    /// inverted searches and by `search_file_stream` and `ReaderSearch`.
+    /// Default: `Line`.
+    pub group_by: Option<GroupBy>,
+    /// Limit in bytes on `text` and context lines, so very long lines (such
+    /// as minified code) stay small. Longer lines are cut without splitting
+    /// UTF-8 characters. `text` keeps the first match when possible, starting
+    /// at it if it would not fit otherwise; see `text_offset` and `truncated`.
+    /// Default: no limit.
+    pub max_line_length: Option<u32>,
+    /// If set, `text` is only the first match on the line plus up to this
+    /// many bytes on each side, for autocomplete-style previews. The snippet
+    /// never splits a UTF-8 character; see `text_offset` and `truncated`.
+    /// Takes precedence over `max_line_length` for `text`. Default: the whole
+    /// line.
+    pub snippet_radius: Option<u32>,
+    /// Budget in bytes for the strings of all returned lines of a file
+    /// together, as a ceiling on the strings allocated for large results.
+    /// Counts `text`, `replaced_text`, `record_text`, the context lines,
+    /// `block`, `heading` and the `bytes` and `captures` of the matches.
+    /// Lines keep their strings in order while they fit; once a line's
+    /// strings do not, it and all later lines get an empty `text` and lose
+    /// the other strings, and `text_truncated` is set. Line numbers and match
+    /// positions are unaffected. Default: no limit.
+    pub max_total_text_bytes: Option<u32>,
+    /// Number of lines of context to return before each matching line, like
+    /// `grep -B`. Requires `include_lines`. Default: 0.
+    pub context_before: Option<u32>,
+    /// Number of lines of context to return after each matching line, like
+    /// `grep -A`. Requires `include_lines`. Default: 0.
+    pub context_after: Option<u32>,
+    /// If true, the context lines are returned joined with the line itself
+    /// into one `block` string, with `block_line` giving the line's position
+    /// in it, instead of as `context_before` and `context_after`. Requires
+    /// `include_lines`. Default: false.
+    pub context_as_block: Option<bool>,
+    /// If true, each context line is returned as its own entry in `lines`,
+    /// next to the line it belongs to, with `is_match` false, `text` set and
+    /// no matches; matching lines get `is_match` true. Suits renderers that
+    /// want one flat list. Ignored with `context_as_block`. Requires
+    /// `include_lines`. Default: false.
+    pub context_as_lines: Option<bool>,
+    /// Regex for heading lines, such as `^\s*(fn|class|def)\b`: each entry in
+    /// `lines` gets `heading`, the text of the nearest line above it that
+    /// matches, as a "match is inside fn foo" breadcrumb. Compiled with the
+    /// same flags as `patterns`. Requires `include_lines`. Default: none.
+    pub heading_pattern: Option<String>,
+    /// If true, select the lines that do *not* match, like `grep -v`. With
+    /// `Or`, a line is selected when no pattern matches it; with `And`, when
+    /// at least one pattern does not match it. Each pattern's `line_numbers`
+    /// lists the selected lines it does not match and `frequency` counts those
+    /// lines. In `lines`, `byte_offset` and `column` point at the line start.
+    /// Default: false.
+    pub invert_match: Option<bool>,
+    /// Files larger than this many bytes are skipped by `search_dir` without
+    /// being opened. Default: no limit.
+    pub max_file_size: Option<i64>,
+    /// Files smaller than this many bytes are skipped by `search_dir` without
+    /// being opened. Default: no limit.
+    pub min_file_size: Option<i64>,
+    /// Files last modified at or before this time, in milliseconds since the
+    /// Unix epoch (`Date.getTime()`), are skipped by `search_dir` without
+    /// being opened. Default: no limit.
+    pub modified_after: Option<f64>,
+    /// If true, `search_dir` skips paths ignored by `.gitignore` (inside git
+    /// repositories), `.ignore` files and git's global and repository
+    /// excludes, like ripgrep. Default: true.
+    pub respect_gitignore: Option<bool>,
+    /// If true, `search_dir` also searches hidden files and directories (names
+    /// starting with `.`). Default: false.
+    pub hidden: Option<bool>,
+    /// If true, `search_dir` follows symbolic links to files and directories.
+    /// Links that form a cycle or cannot be resolved are skipped. If false,
+    /// symbolic links are skipped. Default: false.
+    pub follow_symlinks: Option<bool>,
+    /// If true, `search_files` and `search_dir` also return an entry for each
+    /// file that could not be opened or mapped, with `error` set and no
+    /// matches, instead of leaving it out. Default: false.
+    pub report_errors: Option<bool>,
+    /// How content that looks binary is handled. Content is treated as binary
+    /// if its first 8 KiB contain a NUL byte (see `binary_scan_bytes` and
+    /// `binary_threshold`). Default: `Skip`.
+    pub binary_mode: Option<BinaryMode>,
+    /// Number of leading bytes inspected when deciding whether content is
+    /// binary. Default: 8192.
+    pub binary_scan_bytes: Option<u32>,
+    /// If set, content is binary when more than this fraction (0 to 1) of the
+    /// inspected bytes are control bytes other than tab, line feed, vertical
+    /// tab, form feed, carriage return and escape, instead of when any NUL
+    /// byte is present. Default: none.
+    pub binary_threshold: Option<f64>,
+    /// Encoding of the content. Anything other than `Utf8` is transcoded to
+    /// UTF-8 before searching; a leading UTF-16 byte order mark is dropped
+    /// and invalid UTF-16 is replaced with U+FFFD. Byte offsets and columns
+    /// then refer to the transcoded UTF-8 content, not the original bytes.
+    /// Default: `Utf8`.
+    pub encoding: Option<Encoding>,
+    /// How files are loaded. `Read` and `Auto` support files that cannot be
+    /// memory-mapped, such as those under `/proc` and named pipes; results
+    /// are the same in every mode. Default: `Mmap`.
+    pub read_mode: Option<ReadMode>,
+    /// If set, both the content and the patterns are normalized to this
+    /// Unicode form before matching, so text in NFD matches patterns in NFC
+    /// and vice versa. The content is copied and decoded as UTF-8 (invalid
+    /// sequences become U+FFFD); byte offsets and columns then refer to the
+    /// normalized content. Default: none.
+    pub normalize: Option<Normalization>,
+    /// A 256-entry table every content byte is mapped through before
+    /// matching, for case folding legacy single-byte encodings such as
+    /// CP-1252. The ASCII literals and `\xHH` escapes of the patterns are
+    /// mapped the same way; other non-ASCII pattern characters are not, so
+    /// write non-ASCII bytes as `\xHH`. Only applies when `unicode` is false.
+    /// Line text then shows the mapped bytes. A table without exactly 256
+    /// entries is an error. Default: none.
+    pub byte_map: Option<Vec<u8>>,
+    /// If true, gzip-compressed content (starting with the bytes `1f 8b`) is
+    /// decompressed in memory before searching, and line numbers and offsets
+    /// refer to the decompressed content. Content that fails to decompress is
+    /// searched as is. Default: true.
+    pub decompress: Option<bool>,
+    /// 1-based first line to search. Lines before it are neither matched nor
+    /// returned, but line numbers and offsets stay absolute. Default: 1.
+    pub start_line: Option<u32>,
+    /// 1-based last line to search (inclusive). Default: the last line.
+    pub end_line: Option<u32>,
+    /// Byte offset of the start of the region to search, for when an index
+    /// already narrows down where matches are. Only the region is searched:
+    /// line numbers count from its first line (see `base_line`),
+    /// `start_line` and `end_line` select lines within it, and `total_lines`
+    /// counts its lines, while `byte_offset` stays relative to the whole
+    /// content. Partial lines at either edge are searched as they are.
+    /// Offsets refer to the decompressed and transcoded content. Clamped to
+    /// the content length; negative offsets are an error. Default: 0.
+    pub start_byte: Option<i64>,
+    /// Length in bytes of the region starting at `start_byte`, clamped to the
+    /// end of the content; a negative length is an error. Default: the rest
+    /// of the content.
+    pub byte_length: Option<i64>,
+    /// If set, only the first `head_bytes` bytes of the file as stored are
+    /// searched, for cheaply classifying files by a shebang or magic header.
+    /// Unlike `byte_length`, the rest of the file is never decompressed or
+    /// transcoded, so compressed files cut this way usually fail to
+    /// decompress and are searched as they are stored. Default: the whole file.
+    pub head_bytes: Option<u32>,
+    /// Line number of the first line of the region selected by `start_byte`,
+    /// so line numbers can be reported relative to the whole file when it is
+    /// known. Together with `start_line` it must keep the first searched line
+    /// number within `u32`. Default: 1.
+    pub base_line: Option<u32>,
+    /// With `MatchMode.And`, only lines that have a match of every pattern
+    /// within this many lines of them are returned (0 means on the same
+    /// line), and a file matches only if such a line exists. Per-pattern
+    /// results keep only the matches near a returned line. Ignored with
+    /// `MatchMode.Or` and `invert_match`. Default: no proximity limit.
+    pub within_lines: Option<u32>,
+    /// Byte that ends a line, e.g. 13 for CR-only files or 0 for
+    /// NUL-delimited records. Applies to line numbers, line text and context,
+    /// and to `^`, `$` and `.` in patterns. A trailing `\r` is only trimmed
+    /// from line text when this is `\n`. With 0, NUL bytes do not make
+    /// content count as binary. Default: 10 (`\n`).
+    pub line_terminator: Option<u8>,
+    /// Order of `lines`. `Descending` also reverses each pattern's
+    /// `line_numbers`. `max_results` keeps the lowest line numbers, except
+    /// with `MatchOrder`, where it keeps the first lines found.
+    /// Default: `Ascending`.
+    pub sort: Option<SortOrder>,
+    /// Time budget for the search in milliseconds, measured from the start
+    /// of matching. When it runs out, the search stops and returns what was
+    /// found so far with `partial` set; with `MatchMode.And`, a file whose
+    /// patterns were not all found by then does not match. Files not yet
+    /// started are skipped, and `count_matching_lines` and
+    /// `search_file_stream` stop counting or calling back. Default: no limit.
+    pub deadline_ms: Option<u32>,
+    /// If true, reported line numbers (`line_numbers` and `line_number`)
+    /// start at 0 instead of 1. `start_line` and `end_line` stay 1-based.
+    /// Default: false.
+    pub zero_based: Option<bool>,
+    /// Unit of `column`, `text_offset` and the `matches` columns: bytes,
+    /// Unicode characters, or UTF-16 code units to line up with JavaScript
+    /// string indices into `text`. Invalid UTF-8 counts like its U+FFFD
+    /// replacement in `text`. `byte_offset` is always in bytes.
+    /// Default: `Byte`.
+    pub column_unit: Option<ColumnUnit>,
+    /// If set, columns are visual columns for editors and terminals that
+    /// render tabs as several columns: each tab advances to the next multiple
+    /// of this width, and other characters count in `column_unit`. Applies
+    /// wherever `column_unit` does. Default: none (tabs count as one).
+    pub tab_width: Option<u32>,
+    /// If true, matches may overlap: after each match, the search resumes one
+    /// byte after its start rather than at its end, so `aa` matches `aaaa`
+    /// three times instead of twice. Affects `frequency`, `total_matches` and
+    /// `matches`, not which lines match. Slower, since every position is
+    /// retried. Ignored by `count_matching_lines`, `file_matches`,
+    /// `search_file_stream` and `ReaderSearch`. Default: false.
+    pub overlapping: Option<bool>,
+    /// Maximum number of matches of each pattern collected per line. Once a
+    /// pattern reaches it, scanning for that pattern resumes at the next line,
+    /// which keeps very long lines with many matches (such as minified code)
+    /// cheap. `frequency`, `total_matches` and `matches` only count the
+    /// collected matches. Ignored by `count_matching_lines`, `file_matches`,
+    /// `search_file_stream` and `ReaderSearch`. Default: no limit.
+    pub max_matches_per_line: Option<u32>,
+    /// Replacement for a preview of search-and-replace: each entry in `lines`
+    /// gets `replaced_text`, the line with every match of the first pattern
+    /// replaced. `$1`, `$name` and `${name}` refer to capture groups and `$$`
+    /// is a literal `$`. Only matches within the line are replaced; nothing
+    /// is written to disk. Default: none.
+    pub replacement: Option<String>,
+    /// Approximate limit, in bytes, on the size of each compiled regex.
+    /// Patterns that exceed it fail to compile. Raise it for very large
+    /// alternations. Default: 10 MiB.
+    pub size_limit: Option<u32>,
+    /// Approximate limit, in bytes, on the cache used by each regex's lazy
+    /// DFA. When exhausted, matching falls back to a slower engine.
+    /// Default: 2 MiB.
+    pub dfa_size_limit: Option<u32>,
+}
+
+/// Default for `SearchOptions.size_limit`, matching the regex crate.
+const DEFAULT_SIZE_LIMIT: u32 = 10 * (1 << 20);
+
+/// Default for `SearchOptions.dfa_size_limit`, matching the regex crate.
+const DEFAULT_DFA_SIZE_LIMIT: u32 = 2 * (1 << 20);
+
+/// Default for `SearchOptions.guard_window`.
+const DEFAULT_GUARD_WINDOW: u32 = 64;
+
+/// The line terminator byte selected by `options`.
+fn line_terminator(options: &SearchOptions) -> u8 {
+    options.line_terminator.unwrap_or(b'\n')
 }
 
 // ============================================================================
 // Thread-local regex cache
 // ============================================================================
 
+/// Number of compiled pattern sets each thread keeps. The least recently
+/// used set is evicted when a new one is compiled.
+const CACHE_CAPACITY: usize = 8;
+
 thread_local! {
-    static CACHED: RefCell<Option<CachedSearch>> = RefCell::new(None);
+    /// Cached pattern sets, most recently used first.
+    static CACHED: RefCell<Vec<CachedSearch>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` with the compiled search for these patterns and options, compiling
+/// and caching it first unless the thread-local cache already holds it.
+/// Fails if the byte range options are negative, if any pattern fails to
+/// compile, or if `f` fails.
+fn with_cached_search<T>(
+    patterns: &[String],
+    unicode: bool,
+    case_insensitive: bool,
+    options: &SearchOptions,
+    f: impl FnOnce(&CompiledSearch, Duration) -> Result<T, SearchError>,
+) -> Result<T, SearchError> {
+    let (compiled, compile_time) = cached_search(patterns, unicode, case_insensitive, options)?;
+    f(&compiled, compile_time)
+}
+
+/// The compiled search for these patterns and options from the thread-local
+/// cache, compiled and cached first if needed, with the time compiling took.
+/// The cache is not borrowed while the search is used, so it may be used
+/// while other searches run on the same thread.
+fn cached_search(
+    patterns: &[String],
+    unicode: bool,
+    case_insensitive: bool,
+    options: &SearchOptions,
+) -> Result<(Arc<CompiledSearch>, Duration), SearchError> {
+    check_byte_range(options)?;
+    let cache_key = build_cache_key(patterns, unicode, case_insensitive, options)?;
+    let mut compile_time = Duration::ZERO;
+
+    CACHED.with(|cell| {
+        let mut cache = cell.borrow_mut();
+
+        match cache
+            .iter()
+            .position(|cached| cached.cache_key == cache_key)
+        {
+            Some(0) => {}
+            Some(index) => {
+                let cached = cache.remove(index);
+                cache.insert(0, cached);
+            }
+            None => {
+                let compile_start = Instant::now();
+                let compiled = compile_search(patterns, unicode, case_insensitive, options)?;
+                compile_time = compile_start.elapsed();
+                cache.truncate(CACHE_CAPACITY - 1);
+                cache.insert(
+                    0,
+                    CachedSearch {
+                        cache_key,
+                        compiled: Arc::new(compiled),
+                    },
+                );
+            }
+        }
+
+        Ok((Arc::clone(&cache[0].compiled), compile_time))
+    })
 }
 
 // ============================================================================
-// Exported napi function
+// Exported napi functions
 // ============================================================================
 
 /// Search a file for matches using AND semantics across regex patterns.
@@ -58,6 +1111,7 @@ thread_local! {
 /// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
 ///   use Unicode classes. If false, raw byte mode for maximum performance.
 /// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
 ///
 /// Returns a single-element array with match data, or an empty array on
 /// no match / error.
@@ -67,286 +1121,5143 @@ pub fn search_file_and(
     patterns: Vec<String>,
     unicode: bool,
     case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    search_file(
+        file_path,
+        patterns,
+        MatchMode::And,
+        unicode,
+        case_insensitive,
+        options,
+    )
+}
+
+/// Search a file for matches, combining the patterns according to `mode`.
+///
+/// - `file_path`: Absolute file path to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in the file,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///
+/// Compiled regexes are kept in a thread-local cache, so repeated calls with
+/// the same patterns and flags skip compilation.
+///
+/// Returns a single-element array with match data, or an empty array on
+/// no match / error.
+#[napi]
+pub fn search_file(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
 ) -> Vec<FilePatternMatches> {
     if patterns.is_empty() {
         return Vec::new();
     }
 
-    // Build cache key from patterns + unicode flag.
-    let mut cache_key = patterns.join("\0");
-    cache_key.push('\0');
-    cache_key.push(if unicode { '1' } else { '0' });
-    cache_key.push(if case_insensitive { '1' } else { '0' });
-
-    // Get or compile regexes (thread-local cache)
-    CACHED.with(|cell| {
-        let mut cache = cell.borrow_mut();
+    let options = options.unwrap_or_default();
 
-        let regexes = match &*cache {
-            Some(cached) if cached.cache_key == cache_key => &cached.regexes,
-            _ => {
-                let new_regexes: Result<Vec<Regex>, _> = patterns
-                    .iter()
-                    .map(|pattern| {
-                        regex::bytes::RegexBuilder::new(pattern)
-                            .case_insensitive(case_insensitive)
-                            .multi_line(true)
-                            .unicode(unicode)
-                            .build()
-                    })
-                    .collect();
+    search_file_impl(
+        &file_path,
+        &patterns,
+        mode,
+        unicode,
+        case_insensitive,
+        &options,
+    )
+    .ok()
+    .flatten()
+    .into_iter()
+    .collect()
+}
 
-                // If any pattern fails to compile, return empty results
-                let new_regexes = match new_regexes {
-                    Ok(r) => r,
-                    Err(_) => return Vec::new(),
-                };
+/// Same as `search_file`, but returns the results serialized as a JSON
+/// string, for large result sets where converting every line to a JavaScript
+/// object is slower than one `JSON.parse`. The JSON has the same shape and
+/// property names as the objects `search_file` returns.
+///
+/// Returns `"[]"` on no match / error.
+#[napi]
+pub fn search_file_json(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> String {
+    let results = search_file(
+        file_path,
+        patterns,
+        mode,
+        unicode,
+        case_insensitive,
+        options,
+    );
+    serde_json::to_string(&results).unwrap_or_else(|_| String::from("[]"))
+}
 
-                *cache = Some(CachedSearch {
-                    cache_key,
-                    regexes: new_regexes,
-                });
-                &cache.as_ref().unwrap().regexes
-            }
-        };
+/// Same as `search_file`, but returns the matching lines as parallel typed
+/// arrays (see `FlatMatches`) instead of `LineMatch` objects, for zero-copy
+/// transfer to a worker. `include_lines` is implied; the per-file counts and
+/// per-pattern results are not returned.
+///
+/// Returns `null` on no match / error.
+#[napi]
+pub fn search_file_flat(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Option<FlatMatches> {
+    let options = SearchOptions {
+        include_lines: Some(true),
+        ..options.unwrap_or_default()
+    };
+    let result = search_file(
+        file_path,
+        patterns,
+        mode,
+        unicode,
+        case_insensitive,
+        Some(options.clone()),
+    )
+    .pop()?;
+    let lines = result.lines.unwrap_or_default();
 
-        // Open and mmap the file
-        let file = match std::fs::File::open(&file_path) {
-            Ok(f) => f,
-            Err(_) => return Vec::new(),
-        };
+    let (text, text_offsets) = if options.include_text.unwrap_or(false) {
+        let mut text = Vec::new();
+        let mut text_offsets = vec![0];
+        for line in &lines {
+            text.extend_from_slice(line.text.as_deref().unwrap_or_default().as_bytes());
+            text_offsets.push(text.len() as u32);
+        }
+        (Some(text.into()), Some(text_offsets.into()))
+    } else {
+        (None, None)
+    };
 
-        let mmap = match unsafe { memmap2::Mmap::map(&file) } {
-            Ok(m) => m,
-            Err(_) => return Vec::new(),
-        };
+    Some(FlatMatches {
+        lines: lines
+            .iter()
+            .map(|line| line.line_number)
+            .collect::<Vec<_>>()
+            .into(),
+        offsets: lines
+            .iter()
+            .map(|line| line.byte_offset as f64)
+            .collect::<Vec<_>>()
+            .into(),
+        columns: lines
+            .iter()
+            .map(|line| line.column)
+            .collect::<Vec<_>>()
+            .into(),
+        text,
+        text_offsets,
+    })
+}
 
-        let bytes = &mmap[..];
-        let mut pattern_matches = Vec::new();
+/// Same as `search_file`, but reports failures instead of returning an empty
+/// array. Throws if the file does not exist, cannot be read or mapped, or if
+/// any pattern (or exclude pattern) fails to compile; the error message names
+/// the file or the offending pattern index and the regex error.
+///
+/// Returns a single-element array with match data, or an empty array if the
+/// file genuinely does not match.
+#[napi]
+pub fn search_file_checked(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> napi::Result<Vec<FilePatternMatches>> {
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
 
-        for (idx, regex) in regexes.iter().enumerate() {
-            let match_positions: Vec<usize> =
-                regex.find_iter(bytes).map(|m| m.start()).collect();
+    let options = options.unwrap_or_default();
+    let result = search_file_impl(
+        &file_path,
+        &patterns,
+        mode,
+        unicode,
+        case_insensitive,
+        &options,
+    )?;
 
-            if match_positions.is_empty() {
-                return Vec::new(); // AND failed — early exit
-            }
+    Ok(result.into_iter().collect())
+}
 
-            let frequency = match_positions.len() as u32;
-            let line_numbers = positions_to_line_numbers(bytes, &match_positions);
+/// Search a file and pass each matching line to `callback` as soon as it is
+/// found, instead of building the full result list. This is a synchronous
+/// visitor: `callback` runs on the calling thread during the scan, which
+/// blocks the event loop until the search is done, so large files are best
+/// streamed from a worker. Returns the number of lines passed to `callback`.
+///
+/// - `file_path`: Absolute path to the file to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in the file,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `callback`: Called synchronously with each `LineMatch` in line order.
+///   Returning `false` stops the search.
+/// - `options`: Optional extra settings (see `SearchOptions`). `include_text`,
+///   `max_results` and the line window apply; context lines, `invert_match`,
+///   `within_lines`, `sort` and `replacement` are ignored.
+///
+/// Throws on the same failures as `search_file_checked`, or if `callback`
+/// throws.
+#[napi]
+pub fn search_file_stream(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    callback: Function<LineMatch, Option<bool>>,
+    options: Option<SearchOptions>,
+) -> napi::Result<u32> {
+    if patterns.is_empty() {
+        return Ok(0);
+    }
 
-            pattern_matches.push(PatternMatch {
-                pattern_index: idx as u32,
-                frequency,
-                line_numbers,
-            });
-        }
+    let options = options.unwrap_or_default();
 
-        let total_lines = memchr_iter(b'\n', bytes).count() as u32 + 1;
+    // Held outside the cache so `callback` can run other searches.
+    let (compiled, _) = cached_search(&patterns, unicode, case_insensitive, &options)?;
+    let data = load_file(&file_path, &options)?;
 
-        vec![FilePatternMatches {
-            file_path,
-            total_lines,
-            patterns: pattern_matches,
-        }]
+    let deadline = search_deadline(&options);
+    stream_bytes(&data, &compiled, mode, &options, deadline, |line| {
+        Ok(callback.call(line)? != Some(false))
     })
 }
 
-/// Search multiple files for matches using AND semantics across regex patterns.
-/// All patterns must match somewhere in a file for that file's results to be returned.
-/// Only files with one or more matches are included in the output.
+/// Search an in-memory buffer, combining the patterns according to `mode`.
+/// Behaves exactly like `search_file` on a file with the same content, and
+/// shares its thread-local regex cache.
 ///
-/// - `file_paths`: Array of absolute file paths to search
-/// - `patterns`: Array of regex pattern strings (AND semantics)
+/// - `data`: Content to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in the buffer,
+///   `Or` requires at least one pattern to match.
 /// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
 ///   use Unicode classes. If false, raw byte mode for maximum performance.
 /// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
 ///
-/// Returns an array of `FilePatternMatches` for files where all patterns matched,
+/// Returns a single-element array with match data (with an empty `file_path`),
 /// or an empty array on no match / error.
 #[napi]
-pub fn search_files_and(
-    file_paths: Vec<String>,
+pub fn search_buffer(
+    data: Buffer,
     patterns: Vec<String>,
+    mode: MatchMode,
     unicode: bool,
     case_insensitive: bool,
+    options: Option<SearchOptions>,
 ) -> Vec<FilePatternMatches> {
-    if patterns.is_empty() || file_paths.is_empty() {
+    if patterns.is_empty() {
         return Vec::new();
     }
 
-    // Compile regexes once for the entire batch
-    let regexes: Result<Vec<Regex>, _> = patterns
-        .iter()
-        .map(|pattern| {
-            regex::bytes::RegexBuilder::new(pattern)
-                .case_insensitive(case_insensitive)
-                .multi_line(true)
-                .unicode(unicode)
-                .build()
-        })
-        .collect();
-
-    let regexes = match regexes {
-        Ok(r) => r,
-        Err(_) => return Vec::new(),
-    };
-
-    let mut results = Vec::new();
-
-    for file_path in &file_paths {
-        let file = match std::fs::File::open(file_path) {
-            Ok(f) => f,
-            Err(_) => continue,
-        };
-
-        let mmap = match unsafe { memmap2::Mmap::map(&file) } {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+    let options = options.unwrap_or_default();
 
-        let bytes = &mmap[..];
-        let mut pattern_matches = Vec::new();
-        let mut all_matched = true;
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, compile_time| {
+            let deadline = search_deadline(&options);
+            let mut result = search_bytes("", &data, compiled, mode, &options, deadline);
+            set_compile_time(result.iter_mut(), compile_time);
+            Ok(result)
+        },
+    )
+    .ok()
+    .flatten()
+    .into_iter()
+    .collect()
+}
 
-        for (idx, regex) in regexes.iter().enumerate() {
-            let match_positions: Vec<usize> =
-                regex.find_iter(bytes).map(|m| m.start()).collect();
+/// Search several in-memory strings, such as open editor documents, in
+/// parallel, combining the patterns according to `mode`. Each string behaves
+/// exactly like `search_buffer` on its content.
+///
+/// - `inputs`: Strings to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in a string,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///
+/// Returns an array of `FilePatternMatches` for the matching strings, in
+/// input order, each with `input_index` set and an empty `file_path`.
+/// Returns an empty array on no match / error.
+#[napi]
+pub fn search_strings(
+    inputs: Vec<String>,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    if patterns.is_empty() || inputs.is_empty() {
+        return Vec::new();
+    }
 
-            if match_positions.is_empty() {
-                all_matched = false;
-                break; // AND failed — early exit
-            }
+    let options = options.unwrap_or_default();
 
-            let frequency = match_positions.len() as u32;
-            let line_numbers = positions_to_line_numbers(bytes, &match_positions);
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, compile_time| {
+            let deadline = search_deadline(&options);
+            let mut results: Vec<FilePatternMatches> = inputs
+                .par_iter()
+                .enumerate()
+                .filter_map(|(index, input)| {
+                    if deadline.is_some_and(|d| Instant::now() >= d) {
+                        return None;
+                    }
+                    let mut result =
+                        search_bytes("", input.as_bytes(), compiled, mode, &options, deadline)?;
+                    result.input_index = Some(index as u32);
+                    Some(result)
+                })
+                .collect();
+            set_compile_time(results.iter_mut(), compile_time);
+            Ok(results)
+        },
+    )
+    .unwrap_or_default()
+}
 
-            pattern_matches.push(PatternMatch {
-                pattern_index: idx as u32,
-                frequency,
-                line_numbers,
-            });
-        }
+/// A file kept open, memory-mapped or read into memory, so that several
+/// searches can reuse it without opening it again. Created with `open_file`;
+/// the file stays open until the handle is garbage collected.
+#[napi]
+pub struct FileHandle {
+    file_path: String,
+    data: FileData,
+}
 
-        if all_matched {
-            let total_lines = memchr_iter(b'\n', bytes).count() as u32 + 1;
-            results.push(FilePatternMatches {
-                file_path: file_path.clone(),
-                total_lines,
-                patterns: pattern_matches,
-            });
-        }
+#[napi]
+impl FileHandle {
+    /// Path the file was opened with
+    #[napi(getter)]
+    pub fn file_path(&self) -> String {
+        self.file_path.clone()
     }
+}
 
-    results
+/// Open a file for repeated searches with `search_handle`, for running
+/// several pattern sets against the same large file.
+///
+/// - `file_path`: Absolute path to the file
+/// - `options`: Optional extra settings (see `SearchOptions`). Only
+///   `read_mode` applies.
+///
+/// Throws if the file does not exist or cannot be read or mapped.
+#[napi]
+pub fn open_file(file_path: String, options: Option<SearchOptions>) -> napi::Result<FileHandle> {
+    let data = load_file(&file_path, &options.unwrap_or_default())?;
+    Ok(FileHandle { file_path, data })
 }
 
-/// Search multiple files for matches using OR semantics across regex patterns.
-/// Each pattern is evaluated independently per file. Returns per-pattern
-/// frequency and line number data.
+/// Search a file opened with `open_file`. Behaves exactly like `search_file`
+/// on that file, and shares its thread-local regex cache.
 ///
-/// - `file_paths`: Array of absolute file paths to search
-/// - `patterns`: Array of regex pattern strings (each searched independently)
-/// - `unicode`: If true, use Unicode character classes. False for performance.
+/// - `handle`: File returned by `open_file`
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in the file,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
 /// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). `read_mode` is
+///   ignored.
 ///
-/// Returns an array of `FilePatternMatches` for files with at least one pattern match.
+/// Returns a single-element array with match data, or an empty array on
+/// no match / error.
 #[napi]
-pub fn search_files_or(
-    file_paths: Vec<String>,
+pub fn search_handle(
+    handle: &FileHandle,
     patterns: Vec<String>,
+    mode: MatchMode,
     unicode: bool,
     case_insensitive: bool,
+    options: Option<SearchOptions>,
 ) -> Vec<FilePatternMatches> {
-    if patterns.is_empty() || file_paths.is_empty() {
+    if patterns.is_empty() {
         return Vec::new();
     }
 
-    // Compile regexes once for the entire batch
-    let regexes: Result<Vec<Regex>, _> = patterns
-        .iter()
-        .map(|pattern| {
-            regex::bytes::RegexBuilder::new(pattern)
-                .case_insensitive(case_insensitive)
-                .multi_line(true)
-                .unicode(unicode)
-                .build()
-        })
-        .collect();
-
-    let regexes = match regexes {
-        Ok(r) => r,
-        Err(_) => return Vec::new(),
-    };
-
-    let mut results = Vec::new();
-
-    for file_path in &file_paths {
-        let file = match std::fs::File::open(file_path) {
-            Ok(f) => f,
-            Err(_) => continue,
-        };
+    let options = options.unwrap_or_default();
 
-        let mmap = match unsafe { memmap2::Mmap::map(&file) } {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, compile_time| {
+            let deadline = search_deadline(&options);
+            let mut result = search_bytes(
+                &handle.file_path,
+                &handle.data,
+                compiled,
+                mode,
+                &options,
+                deadline,
+            );
+            set_compile_time(result.iter_mut(), compile_time);
+            Ok(result)
+        },
+    )
+    .ok()
+    .flatten()
+    .into_iter()
+    .collect()
+}
 
-        let bytes = &mmap[..];
+/// Patterns compiled once by `compile_patterns` for any number of searches
+/// with `search_with_set`, independently of the thread-local regex cache.
+#[napi]
+pub struct PatternSet {
+    compiled: CompiledSearch,
+    /// `SearchOptions.line_terminator` the patterns were compiled with
+    line_terminator: Option<u8>,
+}
 
-        let mut pattern_matches = Vec::new();
+#[napi]
+impl PatternSet {
+    /// Number of patterns in the set
+    #[napi(getter)]
+    pub fn pattern_count(&self) -> u32 {
+        self.compiled.regexes.len() as u32
+    }
+}
 
-        for (idx, regex) in regexes.iter().enumerate() {
-            let match_positions: Vec<usize> = regex.find_iter(bytes).map(|m| m.start()).collect();
+/// Compile patterns for repeated searches with `search_with_set`. Unlike
+/// `precompile`, the compiled patterns are never evicted; they are kept until
+/// the returned set is garbage collected.
+///
+/// - `patterns`: Array of regex pattern strings
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Only the
+///   options that affect compilation, such as `whole_word` or
+///   `exclude_patterns`, apply.
+///
+/// Throws if any pattern (or exclude pattern) fails to compile.
+#[napi]
+pub fn compile_patterns(
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> napi::Result<PatternSet> {
+    let options = options.unwrap_or_default();
+    let compiled = compile_search(&patterns, unicode, case_insensitive, &options)?;
+    Ok(PatternSet {
+        compiled,
+        line_terminator: options.line_terminator,
+    })
+}
+
+/// Search a file with patterns compiled by `compile_patterns`, skipping
+/// compilation entirely. Otherwise behaves like `search_file`.
+///
+/// - `set`: Patterns returned by `compile_patterns`
+/// - `file_path`: Absolute path to the file to search
+/// - `mode`: `And` requires every pattern to match somewhere in the file,
+///   `Or` requires at least one pattern to match.
+/// - `options`: Optional extra settings (see `SearchOptions`). Options that
+///   affect compilation, including `line_terminator`, are taken from
+///   `compile_patterns` and ignored here.
+///
+/// Returns a single-element array with match data, or an empty array on
+/// no match / error.
+#[napi]
+pub fn search_with_set(
+    set: &PatternSet,
+    file_path: String,
+    mode: MatchMode,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    if set.compiled.regexes.is_empty() {
+        return Vec::new();
+    }
+
+    let options = SearchOptions {
+        line_terminator: set.line_terminator,
+        ..options.unwrap_or_default()
+    };
+    if check_byte_range(&options).is_err() {
+        return Vec::new();
+    }
+    let Ok(data) = load_file(&file_path, &options) else {
+        return Vec::new();
+    };
+    let deadline = search_deadline(&options);
+    search_bytes(&file_path, &data, &set.compiled, mode, &options, deadline)
+        .into_iter()
+        .collect()
+}
+
+/// Count the lines of a file matched by the patterns, without building any
+/// per-line results. Lines are selected the same way as by `search_file`,
+/// including `invert_match` and `within_lines`: with `And`, the count is 0
+/// unless every pattern matches somewhere; with `Or`, it counts lines matched
+/// by any pattern. Lines matched by several patterns are counted once, and
+/// `max_results` does not cap the count.
+///
+/// - `file_path`: Absolute file path to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: How the patterns are combined (see `search_file`)
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Options that
+///   only shape per-line output are ignored.
+///
+/// Returns the number of distinct matching lines, or 0 on no match / error.
+#[napi]
+pub fn count_matching_lines(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> u32 {
+    if patterns.is_empty() {
+        return 0;
+    }
+
+    let options = options.unwrap_or_default();
+
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            let deadline = search_deadline(&options);
+            Ok(count_lines_in_bytes(
+                &data, compiled, mode, &options, deadline,
+            ))
+        },
+    )
+    .unwrap_or(0)
+}
+
+/// The numbers of the lines of a file matched by the patterns, without
+/// building any per-line results; the cheapest output for minimaps and
+/// heat-maps. Uses the same matching rules as `count_matching_lines`.
+///
+/// - `file_path`: Absolute file path to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: How the patterns are combined (see `search_file`)
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Options that
+///   only shape per-line output are ignored.
+///
+/// Returns the distinct matching line numbers in ascending order, or an empty
+/// array on no match / error.
+#[napi]
+pub fn matching_lines(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<u32> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    let options = options.unwrap_or_default();
+
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            let deadline = search_deadline(&options);
+            Ok(line_numbers_in_bytes(
+                &data, compiled, mode, &options, deadline,
+            ))
+        },
+    )
+    .unwrap_or_default()
+}
+
+/// Check whether a file matches without collecting any results, for gating
+/// logic. Each pattern stops at its first match (outside excluded lines), so
+/// this is much cheaper than a full search.
+///
+/// - `file_path`: Absolute file path to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: How the patterns are combined (see `search_file`)
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Options that
+///   only shape per-line output, `invert_match` and `within_lines` are ignored.
+///
+/// Returns true if the file matches, false on no match / error.
+#[napi]
+pub fn file_matches(
+    file_path: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let options = options.unwrap_or_default();
+
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            let deadline = search_deadline(&options);
+            Ok(bytes_match(&data, compiled, mode, &options, deadline))
+        },
+    )
+    .unwrap_or(false)
+}
+
+/// Report which patterns occur anywhere in a file, for a quick presence
+/// check across many patterns. Each pattern stops at its first match (outside
+/// excluded lines) and no line numbers are computed. Patterns are checked
+/// independently, without the `And` short-circuit, so this also shows which
+/// patterns made an `And` search of the file come back empty.
+///
+/// - `file_path`: Absolute file path to search
+/// - `patterns`: Array of regex pattern strings
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Options that
+///   only shape per-line output, `invert_match` and `within_lines` are ignored.
+///
+/// Returns one boolean per pattern, in input order, telling whether that
+/// pattern matched. All false on error.
+#[napi]
+pub fn pattern_presence(
+    file_path: String,
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<bool> {
+    let options = options.unwrap_or_default();
+
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            let deadline = search_deadline(&options);
+            Ok(patterns_present(&data, compiled, &options, deadline))
+        },
+    )
+    .unwrap_or_else(|_| vec![false; patterns.len()])
+}
+
+/// Search a file for the lines on which a boolean expression over the
+/// patterns holds, such as `(0 AND 1) OR NOT 2`.
+///
+/// - `file_path`: Absolute path to the file to search
+/// - `patterns`: Array of regex pattern strings
+/// - `expression`: Combination of patterns, referenced by index or by the
+///   names in `SearchOptions.pattern_names`, with `AND`, `OR`, `NOT` and
+///   parentheses. `NOT` binds tightest and `OR` loosest.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Each line is
+///   matched on its own, so patterns cannot match across lines. Context
+///   lines (including `context_as_block` and `context_as_lines`),
+///   `heading_pattern`, `invert_match` (use `NOT` instead), `within_lines`,
+///   `group_by`, `from_end`, `skip`, `limit`, `SortOrder::MatchOrder` (lines
+///   stay in line order), `replacement`, `overlapping`,
+///   `max_matches_per_line`, `collapse_ranges`, `record_mode` and
+///   `include_stats` are ignored.
+///
+/// Returns a single-element array whose `patterns` and `lines` cover the
+/// selected lines, or an empty array if no line is selected. Throws if the
+/// expression is invalid, or on the same failures as `search_file_checked`.
+#[napi]
+pub fn search_file_expr(
+    file_path: String,
+    patterns: Vec<String>,
+    expression: String,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> napi::Result<Vec<FilePatternMatches>> {
+    let options = options.unwrap_or_default();
+    let names = options.pattern_names.as_deref().unwrap_or_default();
+    let expr = expr::Expr::parse(&expression, patterns.len(), names)?;
+
+    let result = with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            let deadline = search_deadline(&options);
+            Ok(expr::search_bytes_expr(
+                &file_path, &data, compiled, &expr, &options, deadline,
+            ))
+        },
+    )?;
+
+    Ok(result.into_iter().collect())
+}
+
+/// Convert a byte offset in a file into a line number without running a
+/// search, for mapping offsets reported by other tools. An offset equal to
+/// the file length refers to the end of the last line.
+///
+/// - `file_path`: Absolute path to the file
+/// - `offset`: Byte offset from the start of the file
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///   `line_terminator`, `zero_based` and `read_mode` apply, so the result
+///   agrees with the line numbers `search_file` reports; the other options
+///   are ignored.
+///
+/// Throws if the file cannot be read or `offset` is negative or past the end
+/// of the file.
+#[napi]
+pub fn offset_to_line(
+    file_path: String,
+    offset: i64,
+    options: Option<SearchOptions>,
+) -> napi::Result<u32> {
+    let options = options.unwrap_or_default();
+    if offset < 0 {
+        return Err(SearchError::NegativeByteRange("offset", offset).into());
+    }
+    let data = load_file(&file_path, &options)?;
+    if offset as u64 > data.len() as u64 {
+        return Err(SearchError::OffsetOutOfRange {
+            file_path,
+            offset,
+            length: data.len(),
+        }
+        .into());
+    }
+
+    let line = memchr_iter(line_terminator(&options), &data[..offset as usize]).count() as u32;
+    Ok(if options.zero_based.unwrap_or(false) {
+        line
+    } else {
+        line + 1
+    })
+}
+
+/// Replace every match of the patterns in a file and write the result back.
+/// The patterns are applied in order, each to the output of the previous
+/// one. The file is replaced atomically by writing a temporary file next to
+/// it, flushing it to disk and renaming it over the original, keeping its
+/// permissions. If `file_path` is a symlink, its target is replaced.
+///
+/// - `file_path`: Absolute path to the file to edit
+/// - `patterns`: Array of regex pattern strings
+/// - `replacement`: Replacement for each match. `$1`, `$name` and `${name}`
+///   refer to capture groups and `$$` is a literal `$`.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `dry_run`: If true, only count the replacements; the file is not written.
+/// - `options`: Optional extra settings (see `SearchOptions`). The pattern
+///   options, `exclude_patterns`, `min_line_length`, `guard` and `scope`
+///   apply; the file is edited as raw bytes, so the content and window options
+///   are ignored.
+///
+/// Returns the number of replacements made. The file is left untouched when
+/// there are none. Throws if the file cannot be read or written or a pattern
+/// fails to compile.
+#[napi]
+pub fn replace_in_file(
+    file_path: String,
+    patterns: Vec<String>,
+    replacement: String,
+    unicode: bool,
+    case_insensitive: bool,
+    dry_run: bool,
+    options: Option<SearchOptions>,
+) -> napi::Result<u32> {
+    let options = options.unwrap_or_default();
+
+    let replaced = with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |compiled, _| {
+            let data = load_file(&file_path, &options)?;
+            Ok(replace_bytes(
+                &data,
+                compiled,
+                replacement.as_bytes(),
+                &options,
+            ))
+        },
+    )?;
+
+    match replaced {
+        Some((content, replacements)) => {
+            if !dry_run {
+                write_file_atomic(&file_path, &content)?;
+            }
+            Ok(replacements)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Search multiple files for matches using AND semantics across regex patterns.
+/// All patterns must match somewhere in a file for that file's results to be returned.
+/// Only files with one or more matches are included in the output.
+///
+/// - `file_paths`: Array of absolute file paths to search
+/// - `patterns`: Array of regex pattern strings (AND semantics)
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///
+/// Returns an array of `FilePatternMatches` for files where all patterns matched,
+/// or an empty array on no match / error.
+#[napi]
+pub fn search_files_and(
+    file_paths: Vec<String>,
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    search_files(
+        file_paths,
+        patterns,
+        MatchMode::And,
+        unicode,
+        case_insensitive,
+        options,
+    )
+}
+
+/// Search multiple files for matches using OR semantics across regex patterns.
+/// Each pattern is evaluated independently per file. Returns per-pattern
+/// frequency and line number data.
+///
+/// - `file_paths`: Array of absolute file paths to search
+/// - `patterns`: Array of regex pattern strings (each searched independently)
+/// - `unicode`: If true, use Unicode character classes. False for performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///
+/// Returns an array of `FilePatternMatches` for files with at least one pattern match.
+#[napi]
+pub fn search_files_or(
+    file_paths: Vec<String>,
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    search_files(
+        file_paths,
+        patterns,
+        MatchMode::Or,
+        unicode,
+        case_insensitive,
+        options,
+    )
+}
+
+/// Search multiple files, combining the patterns according to `mode`.
+/// Files are searched in parallel across a thread pool, and results are
+/// returned in the order of `file_paths`. Only files that match are included
+/// in the output.
+///
+/// - `file_paths`: Array of absolute file paths to search
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in a file,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`).
+///
+/// Returns an array of `FilePatternMatches` for matching files, or an empty
+/// array on no match / error.
+#[napi]
+pub fn search_files(
+    file_paths: Vec<String>,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    if patterns.is_empty() || file_paths.is_empty() {
+        return Vec::new();
+    }
+
+    // Compile regexes once for the entire batch. Compiled regexes are safe to
+    // share between threads, so all workers use the same set.
+    let options = options.unwrap_or_default();
+    let compile_start = Instant::now();
+    let compiled = match compile_search(&patterns, unicode, case_insensitive, &options) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+    let compile_time = compile_start.elapsed();
+
+    let mut results = search_file_list(&file_paths, &compiled, mode, &options, |file_path| {
+        file_path
+    });
+    set_compile_time(results.iter_mut(), compile_time);
+    results
+}
+
+/// Recursively search the files under a directory whose relative path matches
+/// a glob, combining the patterns according to `mode`. Files are searched in
+/// parallel like `search_files`.
+///
+/// - `root`: Directory to search
+/// - `glob`: Glob matched against each file's path relative to `root`, e.g.
+///   `**/*.rs`. `*` does not cross directory separators; `**` does.
+/// - `patterns`: Array of regex pattern strings
+/// - `mode`: `And` requires every pattern to match somewhere in a file,
+///   `Or` requires at least one pattern to match.
+/// - `unicode`: If true, `.` matches full Unicode characters and `\w`/`\d`/`\s`
+///   use Unicode classes. If false, raw byte mode for maximum performance.
+/// - `case_insensitive`: If true, matching is case-insensitive.
+/// - `options`: Optional extra settings (see `SearchOptions`). Set
+///   `max_file_size`, `min_file_size` or `modified_after` to skip files by
+///   size or age without opening them.
+///
+/// Returns an array of `FilePatternMatches` for matching files, sorted by
+/// path, with `file_path` relative to `root`. Returns an empty array on no
+/// match / error.
+#[napi]
+pub fn search_dir(
+    root: String,
+    glob: String,
+    patterns: Vec<String>,
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> Vec<FilePatternMatches> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    let options = options.unwrap_or_default();
+    let compile_start = Instant::now();
+    let compiled = match compile_search(&patterns, unicode, case_insensitive, &options) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+    let compile_time = compile_start.elapsed();
+
+    let file_paths = match walk::collect_files(&root, &glob, &options) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut results = search_file_list(&file_paths, &compiled, mode, &options, |file_path| {
+        walk::relative_path(&root, file_path)
+    });
+    set_compile_time(results.iter_mut(), compile_time);
+    results
+}
+
+/// Compile `patterns` and store them in the calling thread's cache without
+/// searching anything, so a later search with the same patterns, flags and
+/// compile options (see `cache_info`) skips compilation. Useful for paying the
+/// compile cost during idle time.
+///
+/// Returns true if every pattern and exclude pattern compiled, false if any
+/// failed or `patterns` is empty.
+#[napi]
+pub fn precompile(
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let options = options.unwrap_or_default();
+    with_cached_search(
+        &patterns,
+        unicode,
+        case_insensitive,
+        &options,
+        |_, _| Ok(()),
+    )
+    .is_ok()
+}
+
+/// Check whether each pattern compiles, with exactly the settings a search
+/// with the same arguments would use. Nothing is cached.
+///
+/// Returns one entry per pattern, in input order: `null` if it compiles,
+/// otherwise the regex error message. Throws if `options.flags` contains an
+/// unknown flag.
+#[napi]
+pub fn validate_patterns(
+    patterns: Vec<String>,
+    unicode: bool,
+    case_insensitive: bool,
+    options: Option<SearchOptions>,
+) -> napi::Result<Vec<Option<String>>> {
+    let options = options.unwrap_or_default();
+    let flags = RegexFlags::new(unicode, case_insensitive, &options)?;
+
+    Ok(patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| {
+            let anchor = flags.anchors.get(index).copied().unwrap_or(Anchor::None);
+            compile_regex(pattern, &flags, anchor)
+                .err()
+                .map(|error| error.to_string())
+        })
+        .collect())
+}
+
+/// Drop the compiled patterns cached on the calling thread, freeing their
+/// memory. The next search compiles its patterns again.
+#[napi]
+pub fn clear_cache() {
+    CACHED.with(|cell| cell.borrow_mut().clear());
+}
+
+/// Describe the compiled patterns cached on the calling thread, for checking
+/// whether repeated searches reuse them.
+#[napi]
+pub fn cache_info() -> CacheInfo {
+    CACHED.with(|cell| {
+        let cache_keys: Vec<String> = cell.borrow().iter().map(|c| c.cache_key.clone()).collect();
+        CacheInfo {
+            has_entry: !cache_keys.is_empty(),
+            cache_key: cache_keys.first().cloned(),
+            cache_keys,
+        }
+    })
+}
+
+// ============================================================================
+// Search core
+// ============================================================================
+
+/// Search files in parallel, reporting each result under the path returned by
+/// `display_path`. Collecting an indexed parallel iterator keeps the results
+/// in input order.
+fn search_file_list<'a>(
+    file_paths: &'a [String],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    display_path: impl Fn(&'a str) -> &'a str + Sync,
+) -> Vec<FilePatternMatches> {
+    let deadline = search_deadline(options);
+    let report_errors = options.report_errors.unwrap_or(false);
+    let summary_only = options.summary_only.unwrap_or(false);
+
+    let summary_options;
+    let options = if summary_only {
+        summary_options = SearchOptions {
+            include_lines: Some(false),
+            collapse_ranges: Some(false),
+            include_total_lines: Some(true),
+            ..options.clone()
+        };
+        &summary_options
+    } else {
+        options
+    };
+
+    file_paths
+        .par_iter()
+        .filter_map(|file_path| {
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return None;
+            }
+            let display_path = display_path(file_path);
+            let data = match load_file(file_path, options) {
+                Ok(data) => data,
+                Err(error) => {
+                    return report_errors.then(|| FilePatternMatches::failed(display_path, &error))
+                }
+            };
+            if summary_only {
+                summarize_bytes(display_path, &data, compiled, mode, options, deadline)
+            } else {
+                search_bytes(display_path, &data, compiled, mode, options, deadline)
+            }
+        })
+        .collect()
+}
+
+/// Search one file using the thread-local regex cache.
+fn search_file_impl(
+    file_path: &str,
+    patterns: &[String],
+    mode: MatchMode,
+    unicode: bool,
+    case_insensitive: bool,
+    options: &SearchOptions,
+) -> Result<Option<FilePatternMatches>, SearchError> {
+    with_cached_search(
+        patterns,
+        unicode,
+        case_insensitive,
+        options,
+        |compiled, compile_time| {
+            let data = load_file(file_path, options)?;
+            let deadline = search_deadline(options);
+            let mut result = search_bytes(file_path, &data, compiled, mode, options, deadline);
+            set_compile_time(result.iter_mut(), compile_time);
+            Ok(result)
+        },
+    )
+}
+
+/// The content of a file, memory-mapped or read into memory.
+enum FileData {
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+}
+
+impl std::ops::Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileData::Mapped(mmap) => mmap,
+            FileData::Read(data) => data,
+        }
+    }
+}
+
+/// Open a file and memory-map it or read it into memory, according to
+/// `options.read_mode`.
+fn load_file(file_path: &str, options: &SearchOptions) -> Result<FileData, SearchError> {
+    let io_error = |error: std::io::Error| {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            SearchError::FileNotFound(file_path.to_string())
+        } else {
+            SearchError::Io {
+                file_path: file_path.to_string(),
+                error,
+            }
+        }
+    };
+
+    let mut file = std::fs::File::open(file_path).map_err(io_error)?;
+    let read_mode = options.read_mode.unwrap_or(ReadMode::Mmap);
+
+    if matches!(read_mode, ReadMode::Auto) {
+        // Special files such as those under /proc report a length of 0 and
+        // named pipes cannot be mapped, so only map non-empty regular files.
+        let mappable = file
+            .metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0);
+        if mappable {
+            if let Ok(mmap) = unsafe { memmap2::Mmap::map(&file) } {
+                return Ok(FileData::Mapped(mmap));
+            }
+        }
+    }
+
+    if matches!(read_mode, ReadMode::Mmap) {
+        return unsafe { memmap2::Mmap::map(&file) }
+            .map(FileData::Mapped)
+            .map_err(io_error);
+    }
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).map_err(io_error)?;
+    Ok(FileData::Read(data))
+}
+
+/// Replace the matches of each compiled pattern in turn, skipping matches
+/// that `ExcludeFilter` rejects. With a byte map, matches are found in the
+/// mapped content but replaced in the original, and capture groups expand to
+/// the mapped bytes. Returns the new content and the number of replacements,
+/// or `None` if nothing was replaced.
+fn replace_bytes(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    replacement: &[u8],
+    options: &SearchOptions,
+) -> Option<(Vec<u8>, u32)> {
+    let mut content = Cow::Borrowed(bytes);
+    let mut replacements = 0;
+
+    for regex in &compiled.regexes {
+        let haystack = map_content(Cow::Borrowed(&content), compiled);
+        let mut exclude_filter = ExcludeFilter::new(compiled, options);
+        let mut replaced = Vec::with_capacity(content.len());
+        let mut last = 0;
+        let mut pattern_replacements = 0;
+
+        for caps in regex.captures_iter(&haystack) {
+            let whole = caps.get(0).unwrap();
+            if exclude_filter.rejects(&haystack, whole.range()) {
+                continue;
+            }
+            replaced.extend_from_slice(&content[last..whole.start()]);
+            caps.expand(replacement, &mut replaced);
+            last = whole.end();
+            pattern_replacements += 1;
+        }
+
+        if pattern_replacements > 0 {
+            replaced.extend_from_slice(&content[last..]);
+            content = Cow::Owned(replaced);
+            replacements += pattern_replacements;
+        }
+    }
+
+    (replacements > 0).then(|| (content.into_owned(), replacements))
+}
+
+/// Replace the file at `file_path` with `content` by writing a temporary file
+/// in the same directory, flushing it to disk and renaming it over the
+/// original, so readers never see a partly written file. The original's
+/// permissions are kept. A symlink is resolved first, so its target is
+/// replaced rather than the link.
+fn write_file_atomic(file_path: &str, content: &[u8]) -> Result<(), SearchError> {
+    // Distinguishes the temporary files of concurrent writes in this process
+    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let io_error = |error: std::io::Error| SearchError::Io {
+        file_path: file_path.to_string(),
+        error,
+    };
+
+    let path = std::fs::canonicalize(file_path).map_err(io_error)?;
+    let permissions = std::fs::metadata(&path).map_err(io_error)?.permissions();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(
+        ".{file_name}.{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut temp_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .map_err(io_error)?;
+    let result = temp_file
+        .write_all(content)
+        .and_then(|_| temp_file.set_permissions(permissions))
+        .and_then(|_| temp_file.sync_all())
+        .and_then(|_| std::fs::rename(&temp_path, &path));
+    drop(temp_file);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result.map_err(io_error)?;
+
+    // Make the rename itself durable. Directories cannot be opened this way
+    // on every platform, so failures here are ignored.
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = std::fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Default number of leading bytes inspected when deciding whether content is
+/// binary.
+const DEFAULT_BINARY_SCAN_BYTES: u32 = 8 * 1024;
+
+/// Cut raw content to `head_bytes`, decompress and transcode it to UTF-8,
+/// then apply binary detection and the byte map of `compiled`. Returns `None`
+/// if the content should be skipped, otherwise the bytes to search.
+fn prepare_content<'a>(
+    bytes: &'a [u8],
+    compiled: &CompiledSearch,
+    options: &SearchOptions,
+) -> Option<Cow<'a, [u8]>> {
+    let content = decode_content(bytes, options)?;
+    Some(map_content(content, compiled))
+}
+
+/// The part of `prepare_content` that does not depend on the patterns.
+fn decode_content<'a>(bytes: &'a [u8], options: &SearchOptions) -> Option<Cow<'a, [u8]>> {
+    let bytes = match options.head_bytes {
+        Some(head_bytes) => &bytes[..bytes.len().min(head_bytes as usize)],
+        None => bytes,
+    };
+    let encoding = options.encoding.unwrap_or(Encoding::Utf8);
+    let decompressed = if options.decompress.unwrap_or(true) {
+        gunzip(bytes)
+    } else {
+        None
+    };
+    let mut content = match decompressed {
+        None => transcode(bytes, encoding),
+        Some(data) if matches!(encoding, Encoding::Utf8) => Cow::Owned(data),
+        Some(data) => Cow::Owned(transcode(&data, encoding).into_owned()),
+    };
+    if let Some(form) = options.normalize {
+        let text = normalize_text(&String::from_utf8_lossy(&content), form);
+        content = Cow::Owned(text.into_bytes());
+    }
+
+    if line_terminator(options) == b'\0' || !looks_binary(&content, options) {
+        return Some(content);
+    }
+
+    match options.binary_mode.unwrap_or(BinaryMode::Skip) {
+        BinaryMode::Skip => None,
+        BinaryMode::Search => Some(content),
+        BinaryMode::TextReplace => {
+            if let Cow::Owned(text) = String::from_utf8_lossy(&content) {
+                return Some(Cow::Owned(text.into_bytes()));
+            }
+            Some(content)
+        }
+    }
+}
+
+/// Map every byte of `content` through the byte map of `compiled`, if any.
+fn map_content<'a>(content: Cow<'a, [u8]>, compiled: &CompiledSearch) -> Cow<'a, [u8]> {
+    match &compiled.byte_map {
+        Some(byte_map) => Cow::Owned(content.iter().map(|&b| byte_map[b as usize]).collect()),
+        None => content,
+    }
+}
+
+/// Returns true if the start of `content` looks binary: it contains a NUL
+/// byte or, with `binary_threshold`, too many control bytes.
+fn looks_binary(content: &[u8], options: &SearchOptions) -> bool {
+    let scan_bytes = options
+        .binary_scan_bytes
+        .unwrap_or(DEFAULT_BINARY_SCAN_BYTES) as usize;
+    let head = &content[..content.len().min(scan_bytes)];
+
+    match options.binary_threshold {
+        None => memchr(b'\0', head).is_some(),
+        Some(_) if head.is_empty() => false,
+        Some(threshold) => {
+            let non_text = head
+                .iter()
+                .filter(|&&b| (b < 0x20 && !matches!(b, b'\t'..=b'\r' | 0x1b)) || b == 0x7f)
+                .count();
+            non_text as f64 / head.len() as f64 > threshold
+        }
+    }
+}
+
+/// Normalize `text` to the Unicode normalization `form`.
+fn normalize_text(text: &str, form: Normalization) -> String {
+    match form {
+        Normalization::Nfc => text.nfc().collect(),
+        Normalization::Nfd => text.nfd().collect(),
+    }
+}
+
+/// Magic bytes at the start of gzip-compressed content.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompress gzip content, including multi-member files. Returns `None` if
+/// `bytes` is not gzip content or fails to decompress.
+fn gunzip(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return None;
+    }
+
+    let mut data = Vec::new();
+    flate2::read::MultiGzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .ok()?;
+    Some(data)
+}
+
+/// Convert content in `encoding` to UTF-8. UTF-8 content is borrowed as is.
+fn transcode(bytes: &[u8], encoding: Encoding) -> Cow<'_, [u8]> {
+    let text = match encoding {
+        Encoding::Utf8 => return Cow::Borrowed(bytes),
+        Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+    };
+    Cow::Owned(text.into_bytes())
+}
+
+/// Decode UTF-16 code units read with `from_bytes`, dropping a leading byte
+/// order mark and replacing unpaired surrogates and a trailing odd byte with
+/// U+FFFD.
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let chunks = bytes.chunks_exact(2);
+    let odd_byte = !chunks.remainder().is_empty();
+    let units = chunks.map(|pair| from_bytes([pair[0], pair[1]]));
+
+    let mut text = String::with_capacity(bytes.len());
+    for c in char::decode_utf16(units) {
+        text.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+    if odd_byte {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+
+    if text.starts_with('\u{FEFF}') {
+        text.drain(..'\u{FEFF}'.len_utf8());
+    }
+    text
+}
+
+/// Per-pattern results, per-line results with `include_lines`, the total
+/// number of distinct matches, and whether a limit left matches out.
+type MatchResults = (Vec<PatternMatch>, Option<Vec<LineMatch>>, u32, bool);
+
+/// Run every regex over `bytes` and combine the per-pattern results
+/// according to `mode`. Returns `None` if the content does not match.
+fn search_bytes(
+    file_path: &str,
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Option<FilePatternMatches> {
+    let head_cut = options
+        .head_bytes
+        .is_some_and(|head_bytes| bytes.len() > head_bytes as usize);
+    let content = prepare_content(bytes, compiled, options)?;
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let mut deadline = DeadlineCheck::new(deadline);
+
+    let match_start = Instant::now();
+    let (mut patterns, mut lines, total_matches, limited) = if options.invert_match.unwrap_or(false)
+    {
+        match_window_inverted(bytes, compiled, mode, options, &mut deadline)?
+    } else {
+        match_window(bytes, compiled, mode, options, &mut deadline)?
+    };
+    let stats = options.include_stats.unwrap_or(false).then(|| SearchStats {
+        bytes_scanned: bytes.len() as u32,
+        match_time_us: duration_us(match_start.elapsed()),
+        compile_time_us: 0,
+    });
+
+    if let (Some(replacement), Some(lines)) = (&options.replacement, &mut lines) {
+        let terminator = line_terminator(options);
+        add_replaced_text(bytes, lines, &compiled.regexes[0], replacement, terminator);
+    }
+    if let (Some(true), Some(lines)) = (options.include_match_bytes, &mut lines) {
+        for line in lines.iter_mut() {
+            let line_start = line.line_start();
+            add_match_bytes(line, &bytes[line_start..]);
+        }
+    }
+    if let (Some(true), Some(lines)) = (options.captures, &mut lines) {
+        for line in lines.iter_mut() {
+            let line_start = line.line_start();
+            add_captures(line, bytes, line_start, &compiled.regexes);
+        }
+    }
+    if let Some(lines) = &mut lines {
+        for line in lines.iter_mut() {
+            let line_start = line.line_start();
+            convert_columns(line, &bytes[line_start..], options);
+        }
+    }
+    window.offset_results(&mut patterns, lines.as_deref_mut());
+
+    let mut matched_lines: Vec<u32> = patterns
+        .iter()
+        .flat_map(|p| p.line_numbers.iter().copied())
+        .collect();
+    matched_lines.sort_unstable();
+    matched_lines.dedup();
+
+    let mut line_ranges = options
+        .collapse_ranges
+        .unwrap_or(false)
+        .then(|| collapse_line_ranges(bytes, &matched_lines, window.lines_before, options));
+
+    if options.zero_based.unwrap_or(false) {
+        for range in line_ranges.iter_mut().flatten() {
+            range.start_line -= 1;
+            range.end_line -= 1;
+        }
+        for pattern in &mut patterns {
+            for line_number in &mut pattern.line_numbers {
+                *line_number -= 1;
+            }
+        }
+        for line in lines.iter_mut().flatten() {
+            shift_line_numbers(line, -1);
+        }
+    }
+
+    if matches!(options.sort, Some(SortOrder::Descending)) {
+        for pattern in &mut patterns {
+            pattern.line_numbers.reverse();
+        }
+        if let Some(lines) = &mut lines {
+            lines.reverse();
+        }
+        if let Some(line_ranges) = &mut line_ranges {
+            line_ranges.reverse();
+        }
+    }
+    let text_truncated = limit_total_text(lines.as_deref_mut().unwrap_or_default(), options);
+
+    let total_lines = if options.include_total_lines.unwrap_or(true) {
+        memchr_iter(line_terminator(options), &content[window.region]).count() as u32 + 1
+    } else {
+        0
+    };
+
+    Some(FilePatternMatches {
+        file_path: file_path.to_string(),
+        total_lines,
+        total_matches,
+        matching_lines: matched_lines.len() as u32,
+        patterns,
+        lines,
+        line_ranges,
+        partial: deadline.expired,
+        truncated: limited || head_cut || deadline.expired,
+        text_truncated,
+        error: None,
+        stats,
+        input_index: None,
+    })
+}
+
+/// Record `compile_time` in the stats of `results` that have them.
+fn set_compile_time<'a>(
+    results: impl Iterator<Item = &'a mut FilePatternMatches>,
+    compile_time: Duration,
+) {
+    for stats in results.filter_map(|result| result.stats.as_mut()) {
+        stats.compile_time_us = duration_us(compile_time);
+    }
+}
+
+/// Convert a duration to whole microseconds, saturating at `u32::MAX`.
+fn duration_us(duration: Duration) -> u32 {
+    u32::try_from(duration.as_micros()).unwrap_or(u32::MAX)
+}
+
+/// Merge the sorted, absolute `line_numbers` into runs of consecutive lines.
+/// `bytes` is the window, whose first line is line `lines_before + 1`. Line
+/// text is added as requested by `options`.
+fn collapse_line_ranges(
+    bytes: &[u8],
+    line_numbers: &[u32],
+    lines_before: u32,
+    options: &SearchOptions,
+) -> Vec<LineRange> {
+    let include_text = options.include_text.unwrap_or(false);
+    let include_lines = options.include_range_lines.unwrap_or(false);
+    let terminator = line_terminator(options);
+    let max_line_length = options.max_line_length;
+
+    let mut ranges: Vec<LineRange> = Vec::new();
+    for &line_number in line_numbers {
+        match ranges.last_mut() {
+            Some(range) if range.end_line + 1 == line_number => range.end_line = line_number,
+            _ => ranges.push(LineRange {
+                start_line: line_number,
+                end_line: line_number,
+                text: None,
+                lines: None,
+            }),
+        }
+    }
+
+    if !include_text && !include_lines {
+        return ranges;
+    }
+
+    // Walk the lines once, in step with the ascending ranges.
+    let mut lines = line_ranges(bytes, terminator).zip(lines_before.saturating_add(1)..);
+    for range in &mut ranges {
+        let run = lines
+            .by_ref()
+            .skip_while(|&(_, n)| n < range.start_line)
+            .take_while(|&(_, n)| n <= range.end_line)
+            .map(|((start, end), _)| line_text(&bytes[start..end], terminator, max_line_length));
+        let texts: Vec<String> = if include_lines {
+            run.collect()
+        } else {
+            run.take(1).collect()
+        };
+
+        range.text = include_text.then(|| texts.first().cloned().unwrap_or_default());
+        range.lines = include_lines.then_some(texts);
+    }
+
+    ranges
+}
+
+/// Run every regex over the window `bytes` and combine the per-pattern
+/// results according to `mode`. Line numbers and offsets are relative to the
+/// window.
+fn match_window(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: &mut DeadlineCheck,
+) -> Option<MatchResults> {
+    let include_lines = options.include_lines.unwrap_or(false);
+    let max_lines = options.max_results.map(|n| n as usize);
+    let terminator = line_terminator(options);
+    let within_lines = match mode {
+        MatchMode::And => options.within_lines,
+        MatchMode::Or => None,
+    };
+    let mut per_pattern = Vec::new();
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    // With a proximity limit, lines are filtered after collection, so the
+    // result limit cannot stop collection early. Neither can it when the last
+    // lines are kept.
+    let from_end = options.from_end.unwrap_or(false);
+    let collect_limit = max_lines.filter(|_| within_lines.is_none() && !from_end);
+
+    // With AND, one pattern that matches nowhere fails the search. Longer
+    // patterns tend to be more specific and so more likely to fail; collect
+    // them first so the search stops before collecting the others.
+    let mut order: Vec<usize> = (0..compiled.regexes.len()).collect();
+    if matches!(mode, MatchMode::And) {
+        order.sort_by_key(|&idx| std::cmp::Reverse(compiled.regexes[idx].as_str().len()));
+    }
+
+    let mut limited = false;
+    for idx in order {
+        let regex = &compiled.regexes[idx];
+        let (match_ranges, pattern_limited) = collect_ranges(
+            bytes,
+            regex,
+            &mut exclude_filter,
+            collect_limit,
+            options,
+            deadline,
+        );
+
+        if match_ranges.is_empty() {
+            match mode {
+                MatchMode::And => return None, // AND failed — early exit
+                MatchMode::Or => continue,
+            }
+        }
+
+        limited |= pattern_limited;
+        let mut match_ranges = match_ranges;
+        let mut line_numbers = ranges_to_line_numbers(bytes, &match_ranges, terminator);
+        if let Some(max_lines) = max_lines.filter(|_| within_lines.is_none() && from_end) {
+            limited |= keep_last_lines(
+                bytes,
+                &mut match_ranges,
+                &mut line_numbers,
+                max_lines,
+                terminator,
+            );
+        }
+        per_pattern.push((idx, match_ranges, line_numbers));
+    }
+
+    if per_pattern.is_empty() {
+        return None;
+    }
+    per_pattern.sort_unstable_by_key(|(idx, ..)| *idx);
+
+    let near_lines = match within_lines {
+        Some(within) => {
+            let pattern_lines: Vec<&[u32]> = per_pattern.iter().map(|p| &p.2[..]).collect();
+            let near_lines = lines_near_all_patterns(&pattern_lines, within);
+            if near_lines.is_empty() {
+                return None;
+            }
+            for (_, ranges, line_numbers) in &mut per_pattern {
+                let mut counter = LineCounter::new(bytes, terminator);
+                ranges.retain(|r| has_line_within(&near_lines, counter.line_at(r.start), within));
+                line_numbers.retain(|&line| has_line_within(&near_lines, line, within));
+            }
+            Some(near_lines)
+        }
+        None => None,
+    };
+
+    // Patterns matching at the same position count as one match.
+    let dedup_positions = options.dedup_positions.unwrap_or(true);
+    let total_matches = if dedup_positions {
+        let mut match_starts: Vec<usize> = per_pattern
+            .iter()
+            .flat_map(|(_, ranges, _)| ranges.iter().map(|r| r.start))
+            .collect();
+        match_starts.sort_unstable();
+        match_starts.dedup();
+        match_starts.len() as u32
+    } else {
+        per_pattern
+            .iter()
+            .map(|(_, ranges, _)| ranges.len() as u32)
+            .sum()
+    };
+
+    let mut pattern_matches = Vec::new();
+    let mut all_ranges = Vec::new();
+
+    for (idx, match_ranges, line_numbers) in per_pattern {
+        pattern_matches.push(PatternMatch {
+            pattern_index: idx as u32,
+            frequency: match_ranges.len() as u32,
+            line_numbers,
+        });
+
+        if !include_lines {
+            continue;
+        }
+        match &near_lines {
+            Some(near_lines) => {
+                // Only matches on the returned lines themselves are listed.
+                let mut counter = LineCounter::new(bytes, terminator);
+                all_ranges.extend(
+                    match_ranges
+                        .into_iter()
+                        .filter(|r| near_lines.binary_search(&counter.line_at(r.start)).is_ok())
+                        .map(|range| (range, idx as u32)),
+                );
+            }
+            None => {
+                all_ranges.extend(match_ranges.into_iter().map(|range| (range, idx as u32)));
+            }
+        }
+    }
+
+    let lines = include_lines.then(|| {
+        let match_order = matches!(options.sort, Some(SortOrder::MatchOrder));
+        let discovery: Vec<usize> = if match_order {
+            all_ranges.iter().map(|(range, _)| range.start).collect()
+        } else {
+            Vec::new()
+        };
+
+        let group_by_match = matches!(options.group_by, Some(GroupBy::Match));
+        let mut lines = ranges_to_line_matches(bytes, &mut all_ranges, terminator, dedup_positions);
+        if !match_order {
+            if let Some(max_lines) = max_lines {
+                limited |= lines.len() > max_lines;
+                if from_end {
+                    lines.drain(..lines.len().saturating_sub(max_lines));
+                } else {
+                    lines.truncate(max_lines);
+                }
+            }
+            if group_by_match {
+                lines = lines
+                    .into_iter()
+                    .flat_map(|line| split_by_match(line, &all_ranges))
+                    .collect();
+            }
+            page_lines(&mut lines, options);
+            add_line_text(bytes, &mut lines, compiled.heading.as_ref(), options);
+            return flatten_context(bytes, lines, options);
+        }
+
+        // Keep the first lines found rather than the lowest line numbers, and
+        // return them in the order they were found. Context is added in line
+        // order so it still never repeats a line.
+        let ranks = discovery_ranks(&lines, &discovery);
+        let mut ranked: Vec<(usize, LineMatch)> = ranks.into_iter().zip(lines).collect();
+        if let Some(max_lines) = max_lines {
+            limited |= ranked.len() > max_lines;
+            ranked.sort_unstable_by_key(|(rank, _)| *rank);
+            if from_end {
+                ranked.drain(..ranked.len().saturating_sub(max_lines));
+            } else {
+                ranked.truncate(max_lines);
+            }
+            ranked.sort_unstable_by_key(|(_, line)| line.line_number);
+        }
+
+        if group_by_match {
+            ranked = ranked
+                .into_iter()
+                .flat_map(|(rank, line)| {
+                    split_by_match(line, &all_ranges).map(move |line| (rank, line))
+                })
+                .collect();
+        }
+
+        let (ranks, mut lines): (Vec<usize>, Vec<LineMatch>) = ranked.into_iter().unzip();
+        add_line_text(bytes, &mut lines, compiled.heading.as_ref(), options);
+
+        // Stable, so the matches of a line stay in column order.
+        let mut ranked: Vec<(usize, LineMatch)> = ranks.into_iter().zip(lines).collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        let mut lines = ranked.into_iter().map(|(_, line)| line).collect();
+        page_lines(&mut lines, options);
+        flatten_context(bytes, lines, options)
+    });
+
+    Some((pattern_matches, lines, total_matches, limited))
+}
+
+/// Keep the matches on the last `max_lines` of `line_numbers`, the sorted
+/// line numbers of the sorted `ranges`, for `from_end`. Returns whether any
+/// lines were dropped.
+fn keep_last_lines(
+    bytes: &[u8],
+    ranges: &mut Vec<Range<usize>>,
+    line_numbers: &mut Vec<u32>,
+    max_lines: usize,
+    terminator: u8,
+) -> bool {
+    let excess = line_numbers.len().saturating_sub(max_lines);
+    if excess == 0 {
+        return false;
+    }
+
+    let first_kept = line_numbers[excess];
+    line_numbers.drain(..excess);
+    let mut counter = LineCounter::new(bytes, terminator);
+    ranges.retain(|r| counter.line_at(r.start) >= first_kept);
+    true
+}
+
+/// Keep the page of `lines` selected by `skip` and `limit`. `lines` are in
+/// the order they are returned, except that with `SortOrder::Descending` they
+/// are still ascending and the page is counted from the end.
+fn page_lines(lines: &mut Vec<LineMatch>, options: &SearchOptions) {
+    if options.skip.is_none() && options.limit.is_none() {
+        return;
+    }
+
+    let skip = options.skip.unwrap_or(0) as usize;
+    let limit = options.limit.map_or(usize::MAX, |n| n as usize);
+    let (start, end) = if matches!(options.sort, Some(SortOrder::Descending)) {
+        let end = lines.len().saturating_sub(skip);
+        (end.saturating_sub(limit), end)
+    } else {
+        let start = skip.min(lines.len());
+        (start, start.saturating_add(limit).min(lines.len()))
+    };
+
+    lines.truncate(end);
+    lines.drain(..start);
+}
+
+/// Split `line` into one entry per match for `GroupBy::Match`, each with its
+/// own `column`, `byte_offset` and the patterns that found that match.
+/// `ranges` holds every match range with its pattern, sorted by range.
+fn split_by_match(
+    line: LineMatch,
+    ranges: &[(Range<usize>, u32)],
+) -> impl Iterator<Item = LineMatch> + '_ {
+    let line_start = line.line_start();
+    let line_number = line.line_number;
+
+    line.matches.into_iter().map(move |m| {
+        let key = (line_start + m.start as usize, line_start + m.end as usize);
+        let first = ranges.partition_point(|(r, _)| (r.start, r.end) < key);
+        let matched_patterns = ranges[first..]
+            .iter()
+            .take_while(|(r, _)| (r.start, r.end) == key)
+            .map(|&(_, idx)| idx)
+            .collect();
+
+        LineMatch::new(
+            line_number,
+            key.0 as i64,
+            m.start,
+            vec![m],
+            matched_patterns,
+        )
+    })
+}
+
+/// For each line of `lines` (sorted by line number), the index into
+/// `starts` of the first match position on that line. `starts` lists match
+/// positions in the order they were found; lines without one get
+/// `usize::MAX`.
+fn discovery_ranks(lines: &[LineMatch], starts: &[usize]) -> Vec<usize> {
+    let mut ranks = vec![usize::MAX; lines.len()];
+
+    for (rank, &start) in starts.iter().enumerate() {
+        let following = lines.partition_point(|l| l.line_start() <= start);
+        if let Some(line) = following.checked_sub(1) {
+            ranks[line] = ranks[line].min(rank);
+        }
+    }
+
+    ranks
+}
+
+/// Lines matched by any pattern that have a match of every pattern within
+/// `within` lines, sorted. `pattern_lines` holds each pattern's sorted line
+/// numbers.
+fn lines_near_all_patterns(pattern_lines: &[&[u32]], within: u32) -> Vec<u32> {
+    let mut candidates: Vec<u32> = pattern_lines.concat();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    candidates.retain(|&line| {
+        pattern_lines
+            .iter()
+            .all(|lines| has_line_within(lines, line, within))
+    });
+    candidates
+}
+
+/// Whether the sorted `lines` contain a line at most `within` lines away from
+/// `line`.
+fn has_line_within(lines: &[u32], line: u32, within: u32) -> bool {
+    let first = lines.partition_point(|&l| l < line.saturating_sub(within));
+    lines
+        .get(first)
+        .is_some_and(|&l| l <= line.saturating_add(within))
+}
+
+/// Inverted (`grep -v`) search of the window `bytes`: test each line against
+/// the patterns and keep the lines that fail them according to `mode`.
+fn match_window_inverted(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: &mut DeadlineCheck,
+) -> Option<MatchResults> {
+    let include_lines = options.include_lines.unwrap_or(false);
+    let max_lines = options.max_results.map_or(usize::MAX, |n| n as usize);
+    let terminator = line_terminator(options);
+    let min_line_length = options.min_line_length.unwrap_or(0);
+    let skip_invalid = skips_invalid_utf8(options);
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+    let mut pattern_lines = vec![Vec::new(); compiled.regexes.len()];
+    let mut line_matches = Vec::new();
+    let mut matched = vec![false; compiled.regexes.len()];
+    let mut selected_lines = 0;
+    let mut limited = false;
+    // With `from_end`, every selected line is kept until the end is known
+    let from_end = options.from_end.unwrap_or(false);
+    let mut selected_numbers = Vec::new();
+
+    for (line_index, (start, end)) in line_ranges(bytes, terminator).enumerate() {
+        if deadline.expired() {
+            break;
+        }
+
+        let line = trim_line_ending(&bytes[start..end], terminator);
+        if is_excluded_line(
+            line,
+            &compiled.exclude_regexes,
+            min_line_length,
+            skip_invalid,
+        ) {
+            continue;
+        }
+
+        let line_range = start..start + line.len();
+        for (is_match, regex) in matched.iter_mut().zip(&compiled.regexes) {
+            *is_match = exclude_filter.line_matches(regex, bytes, line_range.clone());
+        }
+
+        let selected = match mode {
+            MatchMode::And => !matched.iter().all(|&m| m),
+            MatchMode::Or => !matched.iter().any(|&m| m),
+        };
+        if !selected {
+            continue;
+        }
+        if selected_lines >= max_lines && !from_end {
+            limited = true;
+            break;
+        }
+
+        let line_number = line_index as u32 + 1;
+        if from_end {
+            selected_numbers.push(line_number);
+        }
+        for (lines, &is_match) in pattern_lines.iter_mut().zip(&matched) {
+            if !is_match {
+                lines.push(line_number);
+            }
+        }
+
+        if include_lines {
+            line_matches.push(LineMatch::new(
+                line_number,
+                start as i64,
+                0,
+                Vec::new(),
+                matched
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &is_match)| is_match)
+                    .map(|(idx, _)| idx as u32)
+                    .collect(),
+            ));
+        }
+
+        selected_lines += 1;
+    }
+
+    if selected_lines == 0 {
+        return None;
+    }
+    if selected_lines > max_lines {
+        let first_kept = selected_numbers[selected_lines - max_lines];
+        for lines in &mut pattern_lines {
+            lines.retain(|&line| line >= first_kept);
+        }
+        line_matches.retain(|line| line.line_number >= first_kept);
+        selected_lines = max_lines;
+        limited = true;
+    }
+
+    let pattern_matches = pattern_lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(idx, line_numbers)| PatternMatch {
+            pattern_index: idx as u32,
+            frequency: line_numbers.len() as u32,
+            line_numbers,
+        })
+        .collect();
+
+    let lines = include_lines.then(|| {
+        page_lines(&mut line_matches, options);
+        add_line_text(bytes, &mut line_matches, compiled.heading.as_ref(), options);
+        flatten_context(bytes, line_matches, options)
+    });
+
+    Some((pattern_matches, lines, selected_lines as u32, limited))
+}
+
+/// Count the distinct lines matched by the compiled patterns, selected the
+/// same way as by `search_bytes`.
+fn count_lines_in_bytes(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> u32 {
+    let Some(content) = prepare_content(bytes, compiled, options) else {
+        return 0;
+    };
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    selected_line_numbers(bytes, compiled, mode, options, deadline).len() as u32
+}
+
+/// Search `bytes` like `search_bytes` without `include_lines`, but only
+/// count: `patterns` get their `frequency` and empty `line_numbers`, and no
+/// match positions or line numbers are kept. Inverted searches, `within_lines`
+/// with `And` and `from_end` with `max_results` select lines by their
+/// numbers, so those still take the `search_bytes` path.
+fn summarize_bytes(
+    file_path: &str,
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Option<FilePatternMatches> {
+    let by_line_numbers = options.invert_match.unwrap_or(false)
+        || (matches!(mode, MatchMode::And) && options.within_lines.is_some())
+        || (options.from_end.unwrap_or(false) && options.max_results.is_some());
+    if by_line_numbers {
+        let mut result = search_bytes(file_path, bytes, compiled, mode, options, deadline)?;
+        for pattern in &mut result.patterns {
+            pattern.line_numbers = Vec::new();
+        }
+        return Some(result);
+    }
+
+    let head_cut = options
+        .head_bytes
+        .is_some_and(|head_bytes| bytes.len() > head_bytes as usize);
+    let content = prepare_content(bytes, compiled, options)?;
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let mut deadline = DeadlineCheck::new(deadline);
+    let terminator = line_terminator(options);
+    let max_lines = options.max_results.map(|n| n as usize);
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    let match_start = Instant::now();
+    let mut patterns = Vec::new();
+    let mut match_starts = Vec::new();
+    // Most specific patterns first, as in `match_window`
+    let mut order: Vec<usize> = (0..compiled.regexes.len()).collect();
+    if matches!(mode, MatchMode::And) {
+        order.sort_by_key(|&idx| std::cmp::Reverse(compiled.regexes[idx].as_str().len()));
+    }
+
+    let mut limited = false;
+    for idx in order {
+        let regex = &compiled.regexes[idx];
+        let before = match_starts.len();
+        limited |= for_each_match(
+            bytes,
+            regex,
+            &mut exclude_filter,
+            max_lines,
+            options,
+            &mut deadline,
+            |range| match_starts.push(range.start),
+        );
+
+        let frequency = (match_starts.len() - before) as u32;
+        if frequency == 0 {
+            match mode {
+                MatchMode::And => return None, // AND failed — early exit
+                MatchMode::Or => continue,
+            }
+        }
+        patterns.push(PatternMatch {
+            pattern_index: idx as u32,
+            frequency,
+            line_numbers: Vec::new(),
+        });
+    }
+    if patterns.is_empty() {
+        return None;
+    }
+    patterns.sort_unstable_by_key(|p| p.pattern_index);
+    let stats = options.include_stats.unwrap_or(false).then(|| SearchStats {
+        bytes_scanned: bytes.len() as u32,
+        match_time_us: duration_us(match_start.elapsed()),
+        compile_time_us: 0,
+    });
+
+    match_starts.sort_unstable();
+    let total_matches = if options.dedup_positions.unwrap_or(true) {
+        match_starts.dedup();
+        match_starts.len() as u32
+    } else {
+        match_starts.len() as u32
+    };
+
+    // Count each line once by skipping the match starts up to its end.
+    let mut matching_lines = 0;
+    let mut line_end = None;
+    for &start in &match_starts {
+        if line_end.is_some_and(|end| start <= end) {
+            continue;
+        }
+        matching_lines += 1;
+        line_end = Some(line_bounds(bytes, start, terminator).1);
+    }
+
+    let total_lines = if options.include_total_lines.unwrap_or(true) {
+        memchr_iter(terminator, &content[window.region]).count() as u32 + 1
+    } else {
+        0
+    };
+
+    Some(FilePatternMatches {
+        file_path: file_path.to_string(),
+        total_lines,
+        total_matches,
+        matching_lines,
+        patterns,
+        lines: None,
+        line_ranges: None,
+        partial: deadline.expired,
+        truncated: limited || head_cut || deadline.expired,
+        text_truncated: None,
+        error: None,
+        stats,
+        input_index: None,
+    })
+}
+
+/// The sorted line numbers of the lines matched by the compiled patterns,
+/// selected the same way as by `search_bytes`.
+fn line_numbers_in_bytes(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Vec<u32> {
+    let Some(content) = prepare_content(bytes, compiled, options) else {
+        return Vec::new();
+    };
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let first_line =
+        window.lines_before.saturating_add(1) - options.zero_based.unwrap_or(false) as u32;
+
+    selected_line_numbers(bytes, compiled, mode, options, deadline)
+        .into_iter()
+        .map(|line| line - 1 + first_line)
+        .collect()
+}
+
+/// The sorted, window-relative numbers of the lines `search_bytes` would
+/// return for the window `bytes`, honouring `invert_match` and
+/// `within_lines` but not `max_results`.
+fn selected_line_numbers(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Vec<u32> {
+    let mut deadline = DeadlineCheck::new(deadline);
+
+    if options.invert_match.unwrap_or(false) {
+        let options = SearchOptions {
+            include_lines: Some(false),
+            max_results: None,
+            ..options.clone()
+        };
+        let Some((patterns, ..)) =
+            match_window_inverted(bytes, compiled, mode, &options, &mut deadline)
+        else {
+            return Vec::new();
+        };
+        let mut lines: Vec<u32> = patterns.into_iter().flat_map(|p| p.line_numbers).collect();
+        lines.sort_unstable();
+        lines.dedup();
+        return lines;
+    }
+
+    let terminator = line_terminator(options);
+    let pattern_starts = pattern_line_starts(bytes, compiled, mode, options, &mut deadline);
+    let to_line_numbers = |starts: &[usize]| {
+        let mut counter = LineCounter::new(bytes, terminator);
+        starts
+            .iter()
+            .map(|&start| counter.line_at(start))
+            .collect::<Vec<u32>>()
+    };
+
+    match (mode, options.within_lines) {
+        (MatchMode::And, Some(within)) => {
+            let pattern_lines: Vec<Vec<u32>> = pattern_starts
+                .iter()
+                .map(|starts| to_line_numbers(starts))
+                .collect();
+            let pattern_lines: Vec<&[u32]> = pattern_lines.iter().map(|l| &l[..]).collect();
+            lines_near_all_patterns(&pattern_lines, within)
+        }
+        _ => {
+            let mut line_starts = pattern_starts.concat();
+            line_starts.sort_unstable();
+            line_starts.dedup();
+            to_line_numbers(&line_starts)
+        }
+    }
+}
+
+/// Byte offsets of the starts of the distinct lines matched by each compiled
+/// pattern, in ascending order, or none at all when `mode` is `And` and a
+/// pattern matches nowhere. Only one entry per matching line is kept per
+/// pattern, so this stays cheap for files with millions of matches.
+fn pattern_line_starts(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: &mut DeadlineCheck,
+) -> Vec<Vec<usize>> {
+    let terminator = line_terminator(options);
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+    let mut pattern_starts = Vec::new();
+
+    for regex in &compiled.regexes {
+        let mut line_end = None;
+        let mut line_starts = Vec::new();
+
+        for m in regex.find_iter(bytes) {
+            let pos = m.start();
+
+            if deadline.expired() {
+                break;
+            }
+            if line_end.is_some_and(|end| pos <= end) || exclude_filter.rejects(bytes, m.range()) {
+                continue;
+            }
+
+            let (start, end) = line_bounds(bytes, pos, terminator);
+            line_starts.push(start);
+            line_end = Some(end);
+        }
+
+        if line_starts.is_empty() && matches!(mode, MatchMode::And) {
+            return Vec::new(); // AND failed — early exit
+        }
+        pattern_starts.push(line_starts);
+    }
+
+    pattern_starts
+}
+
+/// Whether the compiled patterns match, combined according to `mode`. Stops
+/// at the first match of each pattern that is not on an excluded line, without
+/// computing line numbers.
+fn bytes_match(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> bool {
+    let Some(content) = prepare_content(bytes, compiled, options) else {
+        return false;
+    };
+    let mut deadline = DeadlineCheck::new(deadline);
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    let mut pattern_matches = |regex: &Regex| {
+        regex
+            .find_iter(bytes)
+            .take_while(|_| !deadline.expired())
+            .any(|m| !exclude_filter.rejects(bytes, m.range()))
+    };
+
+    match mode {
+        MatchMode::And => compiled.regexes.iter().all(&mut pattern_matches),
+        MatchMode::Or => compiled.regexes.iter().any(&mut pattern_matches),
+    }
+}
+
+/// Whether each compiled pattern matches, in pattern order. Like
+/// `bytes_match`, each pattern stops at its first match that is not on an
+/// excluded line.
+fn patterns_present(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> Vec<bool> {
+    let Some(content) = prepare_content(bytes, compiled, options) else {
+        return vec![false; compiled.regexes.len()];
+    };
+    let mut deadline = DeadlineCheck::new(deadline);
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    compiled
+        .regexes
+        .iter()
+        .map(|regex| {
+            regex
+                .find_iter(bytes)
+                .take_while(|_| !deadline.expired())
+                .any(|m| !exclude_filter.rejects(bytes, m.range()))
+        })
+        .collect()
+}
+
+/// Fails if `start_byte` or `byte_length` is negative, or if `base_line` and
+/// `start_line` number the first selected line past `u32::MAX`.
+fn check_byte_range(options: &SearchOptions) -> Result<(), SearchError> {
+    let byte_range = [
+        ("startByte", options.start_byte),
+        ("byteLength", options.byte_length),
+    ];
+    for (option, value) in byte_range {
+        if let Some(value) = value.filter(|&value| value < 0) {
+            return Err(SearchError::NegativeByteRange(option, value));
+        }
+    }
+
+    let line_offset = |line: Option<u32>| u64::from(line.unwrap_or(1).max(1)) - 1;
+    let first_line = line_offset(options.base_line) + line_offset(options.start_line) + 1;
+    if first_line > u64::from(u32::MAX) {
+        return Err(SearchError::LineNumberOverflow(first_line));
+    }
+    Ok(())
+}
+
+/// The byte range of the lines selected by `start_byte` and `byte_length`,
+/// and within those by `start_line` and `end_line`.
+struct LineWindow {
+    /// Byte range selected by `start_byte` and `byte_length`, clamped to the
+    /// content
+    region: Range<usize>,
+    /// Byte offset where the first selected line starts
+    start: usize,
+    /// Byte offset just past the last selected line, including its line ending
+    end: usize,
+    /// Number to add to line numbers counted from the first selected line,
+    /// which is line 1
+    lines_before: u32,
+}
+
+impl LineWindow {
+    fn new(bytes: &[u8], options: &SearchOptions) -> Self {
+        // Negative values are rejected by `check_byte_range`.
+        let clamp = |value: i64| value.clamp(0, bytes.len() as i64) as usize;
+        let region_start = options.start_byte.map_or(0, clamp);
+        let region_end = options.byte_length.map_or(bytes.len(), |length| {
+            (region_start + clamp(length)).min(bytes.len())
+        });
+        let bytes = &bytes[region_start..region_end];
+
+        let start_line = options.start_line.unwrap_or(1).max(1);
+        let base_line = options.base_line.unwrap_or(1).max(1);
+        let mut newlines = memchr_iter(line_terminator(options), bytes);
+
+        let start = match start_line {
+            1 => 0,
+            _ => newlines
+                .nth(start_line as usize - 2)
+                .map_or(bytes.len(), |i| i + 1),
+        };
+        let end = match options.end_line {
+            Some(end_line) if end_line < start_line => start,
+            Some(end_line) => newlines
+                .nth((end_line - start_line) as usize)
+                .map_or(bytes.len(), |i| i + 1),
+            None => bytes.len(),
+        };
+
+        LineWindow {
+            region: region_start..region_end,
+            start: region_start + start,
+            end: region_start + end,
+            lines_before: (base_line - 1).saturating_add(start_line - 1),
+        }
+    }
+
+    /// Convert line numbers and byte offsets relative to the window into
+    /// absolute ones.
+    fn offset_results(&self, patterns: &mut [PatternMatch], lines: Option<&mut [LineMatch]>) {
+        if self.start == 0 && self.lines_before == 0 {
+            return;
+        }
+
+        for pattern in patterns {
+            for line_number in &mut pattern.line_numbers {
+                *line_number = line_number.saturating_add(self.lines_before);
+            }
+        }
+        for line in lines.into_iter().flatten() {
+            shift_line_numbers(line, self.lines_before.into());
+            line.byte_offset += self.start as i64;
+        }
+    }
+}
+
+/// Find matching lines in ascending order and pass each to `emit` as soon as
+/// it is complete, merging the matches of all patterns as they are found.
+/// Stops when `emit` returns `false`. Returns the number of lines emitted.
+fn stream_bytes<E>(
+    bytes: &[u8],
+    compiled: &CompiledSearch,
+    mode: MatchMode,
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+    mut emit: impl FnMut(LineMatch) -> Result<bool, E>,
+) -> Result<u32, E> {
+    let mut deadline = DeadlineCheck::new(deadline);
+    let Some(content) = prepare_content(bytes, compiled, options) else {
+        return Ok(0);
+    };
+    let window = LineWindow::new(&content, options);
+    let bytes = &content[window.start..window.end];
+    let terminator = line_terminator(options);
+    let include_text = options.include_text.unwrap_or(false);
+    let zero_based = options.zero_based.unwrap_or(false);
+    let max_lines = options.max_results.unwrap_or(u32::MAX);
+    let dedup_positions = options.dedup_positions.unwrap_or(true);
+    let mut exclude_filter = ExcludeFilter::new(compiled, options);
+
+    if max_lines == 0 {
+        return Ok(0);
+    }
+
+    if matches!(mode, MatchMode::And) {
+        let all_match = compiled.regexes.iter().all(|regex| {
+            regex
+                .find_iter(bytes)
+                .any(|m| !exclude_filter.rejects(bytes, m.range()))
+        });
+        if !all_match {
+            return Ok(0);
+        }
+    }
+
+    let mut iters: Vec<_> = compiled
+        .regexes
+        .iter()
+        .map(|regex| regex.find_iter(bytes).peekable())
+        .collect();
+    let mut counter = LineCounter::new(bytes, terminator);
+    let mut current: Option<(LineMatch, usize)> = None;
+    let mut emitted = 0;
+
+    let finish = |line: LineMatch, line_end: usize| -> LineMatch {
+        let mut line = line;
+        // Sorted and deduplicated like the matches of `search_bytes`
+        line.matches.sort_unstable_by_key(|r| (r.start, r.end));
+        if dedup_positions {
+            line.matches.dedup_by_key(|r| (r.start, r.end));
+        }
+        line.matched_patterns.sort_unstable();
+        line.matched_patterns.dedup();
+        if include_text {
+            let line_start = line.line_start();
+            set_line_text(&mut line, &bytes[line_start..line_end], terminator, options);
+        }
+        if options.include_match_bytes.unwrap_or(false) {
+            let line_start = line.line_start();
+            add_match_bytes(&mut line, &bytes[line_start..]);
+        }
+        let line_start = line.line_start();
+        convert_columns(&mut line, &bytes[line_start..], options);
+        shift_line_numbers(
+            &mut line,
+            i64::from(window.lines_before) - i64::from(zero_based),
+        );
+        line.byte_offset += window.start as i64;
+        line
+    };
+
+    loop {
+        // Take the earliest pending match across all patterns.
+        let next = iters
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(idx, iter)| iter.peek().map(|m| (m.start(), idx)))
+            .min();
+        let Some((_, idx)) = next else { break };
+        let m = iters[idx].next().unwrap();
+
+        if deadline.expired() {
+            break;
+        }
+        if exclude_filter.rejects(bytes, m.range()) {
+            continue;
+        }
+
+        if let Some((line, line_end)) = &mut current {
+            if m.start() <= *line_end {
+                let line_start = line.line_start();
+                line.matches.push(match_range(
+                    bytes,
+                    m.range(),
+                    line_start,
+                    line.line_number,
+                    terminator,
+                ));
+                line.matched_patterns.push(idx as u32);
+                continue;
+            }
+        }
+
+        if let Some((line, line_end)) = current.take() {
+            emitted += 1;
+            if !emit(finish(line, line_end))? || emitted >= max_lines {
+                return Ok(emitted);
+            }
+        }
+
+        let line_number = counter.line_at(m.start());
+        let line_start = counter.line_start();
+        let line_end =
+            memchr(terminator, &bytes[m.start()..]).map_or(bytes.len(), |i| m.start() + i);
+        let match_range = match_range(bytes, m.range(), line_start, line_number, terminator);
+        current = Some((
+            LineMatch::new(
+                line_number,
+                m.start() as i64,
+                match_range.start,
+                vec![match_range],
+                vec![idx as u32],
+            ),
+            line_end,
+        ));
+    }
+
+    if let Some((line, line_end)) = current {
+        if emitted < max_lines {
+            emitted += 1;
+            emit(finish(line, line_end))?;
+        }
+    }
+
+    Ok(emitted)
+}
+
+/// Collect the byte ranges of all matches of `regex`, skipping excluded
+/// lines. With `max_lines` set, stops once matches on that many distinct lines
+/// have been collected. `options.overlapping` and
+/// `options.max_matches_per_line` control where scanning resumes after each
+/// match. Also returns whether either limit left a match out.
+fn collect_ranges(
+    bytes: &[u8],
+    regex: &Regex,
+    exclude_filter: &mut ExcludeFilter,
+    max_lines: Option<usize>,
+    options: &SearchOptions,
+    deadline: &mut DeadlineCheck,
+) -> (Vec<Range<usize>>, bool) {
+    let mut ranges = Vec::new();
+    let limited = for_each_match(
+        bytes,
+        regex,
+        exclude_filter,
+        max_lines,
+        options,
+        deadline,
+        |range| ranges.push(range),
+    );
+    (ranges, limited)
+}
+
+/// Call `found` with each match `collect_ranges` would collect, in order,
+/// without storing them. Returns whether a limit left a match out.
+fn for_each_match(
+    bytes: &[u8],
+    regex: &Regex,
+    exclude_filter: &mut ExcludeFilter,
+    max_lines: Option<usize>,
+    options: &SearchOptions,
+    deadline: &mut DeadlineCheck,
+    mut found: impl FnMut(Range<usize>),
+) -> bool {
+    let terminator = line_terminator(options);
+    let max_per_line = options.max_matches_per_line.map(|n| n.max(1));
+    let track_lines = max_lines.is_some() || max_per_line.is_some();
+    let mut matches = MatchIter::new(regex, bytes, options.overlapping.unwrap_or(false));
+    let mut lines_seen = 0;
+    let mut line_end = 0;
+    let mut line_matches = 0;
+    let mut limited = false;
+
+    while let Some(range) = matches.next() {
+        let pos = range.start;
+
+        if deadline.expired() {
+            break;
+        }
+        if exclude_filter.rejects(bytes, range.clone()) {
+            continue;
+        }
+
+        if track_lines && (lines_seen == 0 || pos > line_end) {
+            lines_seen += 1;
+            if max_lines.is_some_and(|max_lines| lines_seen > max_lines) {
+                limited = true;
+                break;
+            }
+            line_end = line_bounds(bytes, pos, terminator).1;
+            line_matches = 0;
+        }
+
+        // One match past the per-line limit is found before skipping the rest
+        // of the line, so that truncation is only reported when it happened.
+        if max_per_line.is_some_and(|max| line_matches >= max) {
+            limited = true;
+            matches.skip_to(line_end + 1);
+            continue;
+        }
+
+        found(range);
+        line_matches += 1;
+    }
+
+    limited
+}
+
+/// Iterator over the match ranges of a regex with the same semantics as
+/// `Regex::find_iter`, except that it can skip ahead and, with `overlapping`,
+/// resumes one byte after the start of each match instead of at its end.
+struct MatchIter<'a> {
+    regex: &'a Regex,
+    bytes: &'a [u8],
+    overlapping: bool,
+    /// Offset where the next search starts
+    at: usize,
+    /// End of the previous match; an empty match there is skipped
+    last_end: Option<usize>,
+}
+
+impl<'a> MatchIter<'a> {
+    fn new(regex: &'a Regex, bytes: &'a [u8], overlapping: bool) -> Self {
+        MatchIter {
+            regex,
+            bytes,
+            overlapping,
+            at: 0,
+            last_end: None,
+        }
+    }
+
+    /// Continue searching at `pos` or later.
+    fn skip_to(&mut self, pos: usize) {
+        self.at = self.at.max(pos);
+    }
+}
+
+impl Iterator for MatchIter<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        loop {
+            if self.at > self.bytes.len() {
+                return None;
+            }
+            let m = self.regex.find_at(self.bytes, self.at)?;
+            if m.is_empty() && Some(m.end()) == self.last_end {
+                self.at = m.end() + 1;
+                continue;
+            }
+
+            self.at = if self.overlapping {
+                m.start() + 1
+            } else {
+                m.end()
+            };
+            self.last_end = Some(m.end());
+            return Some(m.range());
+        }
+    }
+}
+
+/// The point in time at which a search started now stops, from
+/// `SearchOptions.deadline_ms`.
+fn search_deadline(options: &SearchOptions) -> Option<Instant> {
+    let deadline_ms = options.deadline_ms?;
+    Some(Instant::now() + Duration::from_millis(deadline_ms as u64))
+}
+
+/// Number of `DeadlineCheck::expired` calls between clock reads.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// Checks a search deadline from inside match loops, reading the clock only
+/// every `DEADLINE_CHECK_INTERVAL` calls. Once the deadline has passed, every
+/// later call reports it.
+struct DeadlineCheck {
+    deadline: Option<Instant>,
+    calls: u32,
+    expired: bool,
+}
+
+impl DeadlineCheck {
+    fn new(deadline: Option<Instant>) -> Self {
+        DeadlineCheck {
+            deadline,
+            calls: 0,
+            expired: false,
+        }
+    }
+
+    fn expired(&mut self) -> bool {
+        let Some(deadline) = self.deadline else {
+            return false;
+        };
+
+        if !self.expired {
+            self.calls += 1;
+            if self.calls.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+                self.expired = Instant::now() >= deadline;
+            }
+        }
+        self.expired
+    }
+}
+
+/// Whether a line, without its line ending, is excluded: it matches one of
+/// the exclude regexes, is shorter than `min_line_length` bytes, or is not
+/// valid UTF-8 and `skip_invalid` is set.
+fn is_excluded_line(
+    line: &[u8],
+    exclude_regexes: &[Regex],
+    min_line_length: u32,
+    skip_invalid: bool,
+) -> bool {
+    line.len() < min_line_length as usize
+        || (skip_invalid && std::str::from_utf8(line).is_err())
+        || exclude_regexes.iter().any(|r| r.is_match(line))
+}
+
+/// Whether lines that are not valid UTF-8 are left out of the results.
+fn skips_invalid_utf8(options: &SearchOptions) -> bool {
+    matches!(options.lossy_mode, Some(LossyMode::Skip))
+}
+
+/// Tests matches against the excluded lines, the guard pattern, the scope
+/// pattern and the field boundaries, remembering the result for the most
+/// recent line since consecutive matches often share a line. Must only be
+/// used on one content.
+struct ExcludeFilter<'a> {
+    compiled: &'a CompiledSearch,
+    exclude_regexes: &'a [Regex],
+    guard_window: usize,
+    field_delimiter: Option<u8>,
+    terminator: u8,
+    min_line_length: u32,
+    skip_invalid: bool,
+    /// Byte range of the last tested line and whether it was excluded.
+    last_line: Option<(usize, usize, bool)>,
+    /// Byte ranges matched by the scope pattern, in order, found on first use
+    scope_ranges: Option<Vec<Range<usize>>>,
+}
+
+impl<'a> ExcludeFilter<'a> {
+    fn new(compiled: &'a CompiledSearch, options: &SearchOptions) -> Self {
+        ExcludeFilter {
+            compiled,
+            exclude_regexes: &compiled.exclude_regexes,
+            guard_window: options.guard_window.unwrap_or(DEFAULT_GUARD_WINDOW) as usize,
+            field_delimiter: options.field_delimiter,
+            terminator: line_terminator(options),
+            min_line_length: options.min_line_length.unwrap_or(0),
+            skip_invalid: skips_invalid_utf8(options),
+            last_line: None,
+            scope_ranges: None,
+        }
+    }
+
+    fn is_excluded(&mut self, bytes: &[u8], pos: usize) -> bool {
+        if self.exclude_regexes.is_empty() && self.min_line_length == 0 && !self.skip_invalid {
+            return false;
+        }
+
+        if let Some((start, end, excluded)) = self.last_line {
+            if pos >= start && pos <= end {
+                return excluded;
+            }
+        }
+
+        let (start, end) = line_bounds(bytes, pos, self.terminator);
+        let line = trim_line_ending(&bytes[start..end], self.terminator);
+        let excluded = is_excluded_line(
+            line,
+            self.exclude_regexes,
+            self.min_line_length,
+            self.skip_invalid,
+        );
+
+        self.last_line = Some((start, end, excluded));
+        excluded
+    }
+
+    /// Whether the match at `range` is discarded: its line is excluded, the
+    /// guard matches after it, it starts outside the scope or it is not on
+    /// field boundaries.
+    fn rejects(&mut self, bytes: &[u8], range: Range<usize>) -> bool {
+        !self.on_field_boundaries(bytes, &range)
+            || self.guard_matches(bytes, range.end)
+            || !self.in_scope(bytes, range.start)
+            || self.is_excluded(bytes, range.start)
+    }
+
+    /// Whether `regex` has a match in the line at `line` of `bytes` that is
+    /// not discarded by the guard, scope or field boundaries. Line exclusion
+    /// is not checked.
+    fn line_matches(&mut self, regex: &Regex, bytes: &[u8], line: Range<usize>) -> bool {
+        let text = &bytes[line.clone()];
+        if self.compiled.guard.is_none()
+            && self.compiled.scope.is_none()
+            && self.field_delimiter.is_none()
+        {
+            return regex.is_match(text);
+        }
+        regex.find_iter(text).any(|m| {
+            let range = line.start + m.start()..line.start + m.end();
+            self.on_field_boundaries(bytes, &range)
+                && !self.guard_matches(bytes, range.end)
+                && self.in_scope(bytes, range.start)
+        })
+    }
+
+    /// Whether `range` starts and ends next to the field delimiter or a line
+    /// boundary. Always true without a delimiter.
+    fn on_field_boundaries(&self, bytes: &[u8], range: &Range<usize>) -> bool {
+        let Some(delimiter) = self.field_delimiter else {
+            return true;
+        };
+        let is_boundary = |b: u8| b == delimiter || b == self.terminator;
+
+        let starts_field = range.start == 0 || is_boundary(bytes[range.start - 1]);
+        let ends_field = match bytes.get(range.end..) {
+            None | Some([]) => true,
+            // The `\r` of a CRLF line ending
+            Some([b'\r', rest @ ..]) if self.terminator == b'\n' => {
+                rest.first().is_none_or(|&b| b == b'\n')
+            }
+            Some([b, ..]) => is_boundary(*b),
+        };
+        starts_field && ends_field
+    }
+
+    /// Whether the guard matches within the guard window after `end`.
+    fn guard_matches(&self, bytes: &[u8], end: usize) -> bool {
+        self.compiled.guard.as_ref().is_some_and(|guard| {
+            let window_end = bytes.len().min(end.saturating_add(self.guard_window));
+            guard.is_match(&bytes[end..window_end])
+        })
+    }
+
+    /// Whether `pos` falls inside a match of the scope pattern. Always true
+    /// without one.
+    fn in_scope(&mut self, bytes: &[u8], pos: usize) -> bool {
+        let Some(scope) = &self.compiled.scope else {
+            return true;
+        };
+        let ranges = self
+            .scope_ranges
+            .get_or_insert_with(|| scope.find_iter(bytes).map(|m| m.range()).collect());
+        let index = ranges.partition_point(|range| range.end <= pos);
+        ranges.get(index).is_some_and(|range| range.start <= pos)
+    }
+}
+
+// ============================================================================
+// Regex compilation
+// ============================================================================
+
+/// Every setting that affects how patterns compile, resolved from the search
+/// arguments and `SearchOptions` with their defaults applied. Keeping them in
+/// one place means the cache key cannot miss a setting the compiler uses.
+/// Options that only shape the results, such as `include_lines`,
+/// `max_line_length`, `snippet_radius`, `sort`, `skip` and `limit`, must stay
+/// out of here so that changing them never recompiles.
+#[derive(Debug)]
+struct RegexFlags {
+    /// Unicode `.`, Perl classes and word boundaries
+    unicode: bool,
+    unicode_case: bool,
+    case_insensitive: bool,
+    smart_case: bool,
+    whole_word: bool,
+    literal: bool,
+    full_line: bool,
+    line_prefix: bool,
+    /// Per-pattern anchors; only applied to the search patterns, not to
+    /// exclude patterns.
+    anchors: Vec<Anchor>,
+    multi_line: bool,
+    dot_all: bool,
+    normalize: Option<Normalization>,
+    /// Only set in byte mode.
+    byte_map: Option<Vec<u8>>,
+    size_limit: usize,
+    dfa_size_limit: usize,
+    line_terminator: u8,
+}
+
+impl RegexFlags {
+    /// Fails if `options.flags` contains an unknown flag or
+    /// `options.byte_map` does not have 256 entries.
+    fn new(
+        unicode: bool,
+        case_insensitive: bool,
+        options: &SearchOptions,
+    ) -> Result<Self, SearchError> {
+        let mut flags = RegexFlags {
+            unicode: options.unicode_dot.unwrap_or(unicode),
+            unicode_case: options.unicode_case.unwrap_or(unicode),
+            case_insensitive,
+            smart_case: options.smart_case.unwrap_or(false),
+            whole_word: options.whole_word.unwrap_or(false),
+            literal: options.literal.unwrap_or(false),
+            full_line: options.full_line_match.unwrap_or(false),
+            line_prefix: options.line_prefix.unwrap_or(false),
+            anchors: options.anchors.clone().unwrap_or_default(),
+            multi_line: options.multi_line.unwrap_or(true),
+            dot_all: options.dot_all.unwrap_or(false),
+            normalize: options.normalize,
+            byte_map: None,
+            size_limit: options.size_limit.unwrap_or(DEFAULT_SIZE_LIMIT) as usize,
+            dfa_size_limit: options.dfa_size_limit.unwrap_or(DEFAULT_DFA_SIZE_LIMIT) as usize,
+            line_terminator: line_terminator(options),
+        };
+        if let Some(short_flags) = &options.flags {
+            flags.parse_flags(short_flags)?;
+        }
+        if let Some(byte_map) = &options.byte_map {
+            if byte_map.len() != 256 {
+                return Err(SearchError::InvalidByteMap(byte_map.len()));
+            }
+            if !flags.unicode {
+                flags.byte_map = Some(byte_map.clone());
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Turn on the setting named by each character of a ripgrep-style flags
+    /// string such as `"wiF"`. Flags only ever turn settings on, so any
+    /// combination is accepted and repeating a flag changes nothing. Where
+    /// two settings conflict the usual precedence applies: `S` wins over `i`
+    /// as `smart_case` does over `case_insensitive`, and `x` over a
+    /// `line_prefix` option as `full_line_match` does.
+    fn parse_flags(&mut self, flags: &str) -> Result<(), SearchError> {
+        for flag in flags.chars() {
+            match flag {
+                'i' => self.case_insensitive = true,
+                'S' => self.smart_case = true,
+                'w' => self.whole_word = true,
+                'x' => self.full_line = true,
+                'F' => self.literal = true,
+                's' => self.dot_all = true,
+                _ => return Err(SearchError::InvalidFlag(flag)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build the regex cache key: each pattern string tagged with its role and
+/// prefixed with its length, so no pattern can run into the next, followed by
+/// \0 and the `RegexFlags` in `Debug` form. Search patterns are tagged `p`,
+/// exclude patterns `x`, and the guard, scope and heading patterns `g`, `s`
+/// and `h`.
+fn build_cache_key(
+    patterns: &[String],
+    unicode: bool,
+    case_insensitive: bool,
+    options: &SearchOptions,
+) -> Result<String, SearchError> {
+    let flags = RegexFlags::new(unicode, case_insensitive, options)?;
+    let mut cache_key = String::new();
+    let tagged_patterns = patterns
+        .iter()
+        .map(|pattern| ('p', pattern))
+        .chain(
+            options
+                .exclude_patterns
+                .iter()
+                .flatten()
+                .map(|pattern| ('x', pattern)),
+        )
+        .chain(options.guard.iter().map(|pattern| ('g', pattern)))
+        .chain(options.scope.iter().map(|pattern| ('s', pattern)))
+        .chain(options.heading_pattern.iter().map(|pattern| ('h', pattern)));
+    for (tag, pattern) in tagged_patterns {
+        cache_key.push_str(&format!("{tag}{}:{pattern}", pattern.len()));
+    }
+    cache_key.push('\0');
+    cache_key.push_str(&format!("{flags:?}"));
+    Ok(cache_key)
+}
+
+/// Compile the search patterns, exclude patterns and the guard, scope and
+/// heading patterns. Fails if any pattern fails to compile.
+fn compile_search(
+    patterns: &[String],
+    unicode: bool,
+    case_insensitive: bool,
+    options: &SearchOptions,
+) -> Result<CompiledSearch, SearchError> {
+    let flags = RegexFlags::new(unicode, case_insensitive, options)?;
+    let regexes = compile_regexes(patterns, &flags, &flags.anchors)
+        .map_err(|(index, error)| SearchError::InvalidPattern { index, error })?;
+    let exclude_regexes = match &options.exclude_patterns {
+        Some(exclude) => compile_regexes(exclude, &flags, &[])
+            .map_err(|(index, error)| SearchError::InvalidExcludePattern { index, error })?,
+        None => Vec::new(),
+    };
+
+    let compile_optional = |pattern: &Option<String>, error: fn(regex::Error) -> SearchError| {
+        pattern
+            .as_deref()
+            .map(|pattern| compile_regex(pattern, &flags, Anchor::None).map_err(error))
+            .transpose()
+    };
+
+    Ok(CompiledSearch {
+        regexes,
+        exclude_regexes,
+        guard: compile_optional(&options.guard, SearchError::InvalidGuardPattern)?,
+        scope: compile_optional(&options.scope, SearchError::InvalidScopePattern)?,
+        heading: compile_optional(&options.heading_pattern, SearchError::InvalidHeadingPattern)?,
+        byte_map: flags.byte_map.clone(),
+    })
+}
+
+/// Compile every pattern with the given flags, anchoring each as given by
+/// `anchors`. Fails with the index and error of the first pattern that fails
+/// to compile.
+fn compile_regexes(
+    patterns: &[String],
+    flags: &RegexFlags,
+    anchors: &[Anchor],
+) -> Result<Vec<Regex>, (usize, regex::Error)> {
+    patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| {
+            let anchor = anchors.get(index).copied().unwrap_or(Anchor::None);
+            compile_regex(pattern, flags, anchor).map_err(|error| (index, error))
+        })
+        .collect()
+}
+
+/// Compile one pattern with the given flags and anchor, after applying the
+/// pattern-rewriting flags.
+fn compile_regex(pattern: &str, flags: &RegexFlags, anchor: Anchor) -> Result<Regex, regex::Error> {
+    // Lets `$` match before the `\r` of a CRLF line ending.
+    let anchors_end = flags.full_line || matches!(anchor, Anchor::LineEnd | Anchor::FullLine);
+    let crlf = anchors_end && flags.line_terminator == b'\n';
+
+    let pattern = match flags.normalize {
+        Some(form) => Cow::Owned(normalize_text(pattern, form)),
+        None => Cow::Borrowed(pattern),
+    };
+    let pattern = anchor_pattern(wrap_pattern(&pattern, flags), anchor);
+
+    // Checked after wrapping so escaped literals are seen as plain text.
+    let case_insensitive = if flags.smart_case {
+        !pattern_has_uppercase(&pattern)
+    } else {
+        flags.case_insensitive
+    };
+    let pattern = match &flags.byte_map {
+        Some(byte_map) => Cow::Owned(map_pattern(&pattern, byte_map)),
+        None => pattern,
+    };
+
+    // The regex engine ties case folding to the `unicode` flag, so other
+    // combinations are compiled case-sensitively with the folding spelled out.
+    let fold_separately = case_insensitive && flags.unicode_case != flags.unicode;
+    let pattern = if fold_separately {
+        Cow::Owned(fold_pattern(&pattern, flags)?)
+    } else {
+        pattern
+    };
+
+    regex::bytes::RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive && !fold_separately)
+        .multi_line(flags.multi_line)
+        .dot_matches_new_line(flags.dot_all)
+        .unicode(flags.unicode)
+        .line_terminator(flags.line_terminator)
+        .crlf(crlf)
+        .size_limit(flags.size_limit)
+        .dfa_size_limit(flags.dfa_size_limit)
+        .build()
+}
+
+/// Apply the pattern-rewriting flags (literal, whole word, full line, line
+/// prefix) to a user pattern. The pattern is grouped first so alternations and
+/// anchors stay intact.
+fn wrap_pattern<'a>(pattern: &'a str, flags: &RegexFlags) -> Cow<'a, str> {
+    let mut pattern = Cow::Borrowed(pattern);
+
+    if flags.literal {
+        pattern = Cow::Owned(regex::escape(&pattern));
+    }
+
+    if flags.whole_word {
+        pattern = Cow::Owned(format!(r"(?:\b(?:{pattern})\b)"));
+    }
+
+    if flags.full_line {
+        pattern = Cow::Owned(format!("(?m:^(?:{pattern})$)"));
+    } else if flags.line_prefix {
+        pattern = Cow::Owned(format!("(?m:^(?:{pattern}))"));
+    }
+
+    pattern
+}
+
+/// Anchor a wrapped pattern to the line as given by `anchor`.
+fn anchor_pattern(pattern: Cow<'_, str>, anchor: Anchor) -> Cow<'_, str> {
+    match anchor {
+        Anchor::None => pattern,
+        Anchor::LineStart => Cow::Owned(format!("(?m:^(?:{pattern}))")),
+        Anchor::LineEnd => Cow::Owned(format!("(?m:(?:{pattern})$)")),
+        Anchor::FullLine => Cow::Owned(format!("(?m:^(?:{pattern})$)")),
+    }
+}
+
+/// Returns true if the pattern contains an uppercase character outside of
+/// escape sequences such as `\W`, `\p{Lu}` or `\x4A`. Used for smart case.
+fn pattern_has_uppercase(pattern: &str) -> bool {
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if c.is_uppercase() {
+                return true;
+            }
+            continue;
+        }
+
+        // Skip the escaped character plus any class name or code point
+        // that follows it.
+        let fixed_len = match chars.next() {
+            Some('p' | 'P') => 1,
+            Some('x') => 2,
+            Some('u') => 4,
+            Some('U') => 8,
+            _ => 0,
+        };
+
+        if fixed_len > 0 {
+            if chars.peek() == Some(&'{') {
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+            } else {
+                for _ in 0..fixed_len {
+                    chars.next();
+                }
+            }
+        }
+    }
+
+    false
+}
+
+/// Rewrite `pattern` so it matches content mapped through `byte_map`: ASCII
+/// literals, escaped punctuation and `\xHH` escapes are replaced by their
+/// mapped byte. Other escapes, group flags and names, repetition counts and
+/// POSIX class names are kept as is, as are metacharacters and non-ASCII
+/// characters.
+fn map_pattern(pattern: &str, byte_map: &[u8]) -> String {
+    let mut mapped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    let mut class_depth = 0usize;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let Some(escaped) = chars.next() else {
+                    mapped.push(c);
+                    break;
+                };
+                if escaped.is_ascii_punctuation() {
+                    match byte_map[escaped as usize] {
+                        byte if byte == escaped as u8 => {
+                            mapped.push(c);
+                            mapped.push(escaped);
+                        }
+                        byte => mapped.push_str(&format!("\\x{byte:02X}")),
+                    }
+                    continue;
+                }
+                let fixed_len = match escaped {
+                    'x' => 2,
+                    'p' | 'P' => 1,
+                    'u' => 4,
+                    'U' => 8,
+                    _ => 0,
+                };
+
+                let mut code = String::new();
+                if fixed_len > 0 && chars.peek() == Some(&'{') {
+                    chars.next();
+                    code.push('{');
+                    copy_through(&mut code, &mut chars, &['}']);
+                } else {
+                    for _ in 0..fixed_len {
+                        code.extend(chars.next_if(|c| c.is_ascii_alphanumeric()));
+                    }
+                }
+
+                let digits = code.trim_start_matches('{').trim_end_matches('}');
+                match u8::from_str_radix(digits, 16) {
+                    Ok(byte) if escaped == 'x' => {
+                        mapped.push_str(&format!("\\x{:02X}", byte_map[byte as usize]));
+                    }
+                    _ => {
+                        mapped.push(c);
+                        mapped.push(escaped);
+                        mapped.push_str(&code);
+                    }
+                }
+            }
+            '(' if chars.peek() == Some(&'?') => {
+                mapped.push(c);
+                copy_through(&mut mapped, &mut chars, &[':', ')', '>']);
+            }
+            '{' => {
+                mapped.push(c);
+                copy_through(&mut mapped, &mut chars, &['}']);
+            }
+            '[' if class_depth > 0 && chars.peek() == Some(&':') => {
+                mapped.push(c);
+                copy_through(&mut mapped, &mut chars, &[']']);
+            }
+            '[' => {
+                class_depth += 1;
+                mapped.push(c);
+            }
+            ']' => {
+                class_depth = class_depth.saturating_sub(1);
+                mapped.push(c);
+            }
+            c if c.is_ascii_graphic() && !is_meta_character(c) => {
+                let byte = byte_map[c as usize];
+                if byte == c as u8 {
+                    mapped.push(c);
+                } else {
+                    mapped.push_str(&format!("\\x{byte:02X}"));
+                }
+            }
+            c => mapped.push(c),
+        }
+    }
+
+    mapped
+}
+
+/// Rewrite `pattern` so it matches case-insensitively with the case folding
+/// of `flags.unicode_case` when compiled case-sensitively, keeping the
+/// `flags.unicode` meaning of `.` and classes. Every letter literal and
+/// outermost class is replaced by its folded class in a `(?u:...)` group;
+/// negated classes are folded before they are negated.
+fn fold_pattern(pattern: &str, flags: &RegexFlags) -> Result<String, regex::Error> {
+    let ast = ast::parse::Parser::new()
+        .parse(pattern)
+        .map_err(|error| regex::Error::Syntax(error.to_string()))?;
+
+    let scope = FoldScope {
+        unicode: flags.unicode,
+        case_insensitive: true,
+    };
+    let mut replacements = Vec::new();
+    fold_ast(&ast, pattern, scope, flags.unicode_case, &mut replacements)?;
+
+    let mut folded = String::with_capacity(pattern.len());
+    let mut last = 0;
+    for (span, replacement) in replacements {
+        folded.push_str(&pattern[last..span.start.offset]);
+        folded.push_str(&replacement);
+        last = span.end.offset;
+    }
+    folded.push_str(&pattern[last..]);
+    Ok(folded)
+}
+
+/// Inline flags in effect at a point of a pattern, as far as folding goes.
+#[derive(Clone, Copy)]
+struct FoldScope {
+    unicode: bool,
+    case_insensitive: bool,
+}
+
+impl FoldScope {
+    fn apply(&mut self, flags: &ast::Flags) {
+        if let Some(unicode) = flags.flag_state(ast::Flag::Unicode) {
+            self.unicode = unicode;
+        }
+        if let Some(case_insensitive) = flags.flag_state(ast::Flag::CaseInsensitive) {
+            self.case_insensitive = case_insensitive;
+        }
+    }
+}
+
+/// Collect the folded replacement of each letter literal and outermost class
+/// in `ast`, in pattern order. Parts where inline flags turn case
+/// insensitivity off are left as written.
+fn fold_ast(
+    ast: &Ast,
+    pattern: &str,
+    scope: FoldScope,
+    unicode_case: bool,
+    replacements: &mut Vec<(ast::Span, String)>,
+) -> Result<(), regex::Error> {
+    let (span, negated, positive) = match ast {
+        Ast::Literal(literal) => {
+            // Bytes written as `\xHH` escapes in byte mode have no case
+            let byte = !scope.unicode && literal.byte().is_some_and(|b| b > 0x7F);
+            if byte || !scope.case_insensitive {
+                return Ok(());
+            }
+            let c = literal.c;
+            let class = Class::Unicode(ClassUnicode::new([ClassUnicodeRange::new(c, c)]));
+            let folded = fold_class(class, unicode_case);
+            if folded.literal().is_none() {
+                replacements.push((literal.span, print_class(folded)?));
+            }
+            return Ok(());
+        }
+        Ast::ClassUnicode(class) => (
+            class.span,
+            class.negated,
+            Ast::class_unicode(ast::ClassUnicode {
+                negated: false,
+                ..(**class).clone()
+            }),
+        ),
+        Ast::ClassPerl(class) => (
+            class.span,
+            class.negated,
+            Ast::class_perl(ast::ClassPerl {
+                negated: false,
+                ..(**class).clone()
+            }),
+        ),
+        Ast::ClassBracketed(class) => (
+            class.span,
+            class.negated,
+            Ast::class_bracketed(ast::ClassBracketed {
+                negated: false,
+                ..(**class).clone()
+            }),
+        ),
+        Ast::Repetition(repetition) => {
+            return fold_ast(&repetition.ast, pattern, scope, unicode_case, replacements);
+        }
+        Ast::Group(group) => {
+            let mut scope = scope;
+            if let Some(flags) = group.flags() {
+                scope.apply(flags);
+            }
+            return fold_ast(&group.ast, pattern, scope, unicode_case, replacements);
+        }
+        Ast::Alternation(alternation) => {
+            for ast in &alternation.asts {
+                fold_ast(ast, pattern, scope, unicode_case, replacements)?;
+            }
+            return Ok(());
+        }
+        Ast::Concat(concat) => {
+            // Flags set part way through apply to the rest of the group
+            let mut scope = scope;
+            for ast in &concat.asts {
+                if let Ast::Flags(set) = ast {
+                    scope.apply(&set.flags);
+                }
+                fold_ast(ast, pattern, scope, unicode_case, replacements)?;
+            }
+            return Ok(());
+        }
+        Ast::Empty(_) | Ast::Flags(_) | Ast::Dot(_) | Ast::Assertion(_) => return Ok(()),
+    };
+
+    if !scope.case_insensitive {
+        return Ok(());
+    }
+    let folded = fold_class_ast(&positive, negated, pattern, scope.unicode, unicode_case)?;
+    replacements.push((span, folded));
+    Ok(())
+}
+
+/// Translate the non-negated form of a class, fold it and negate it again if
+/// `negated`, so negated classes exclude every case of their members.
+fn fold_class_ast(
+    positive: &Ast,
+    negated: bool,
+    pattern: &str,
+    unicode: bool,
+    unicode_case: bool,
+) -> Result<String, regex::Error> {
+    let hir = TranslatorBuilder::new()
+        .unicode(unicode)
+        .utf8(false)
+        .build()
+        .translate(pattern, positive)
+        .map_err(|error| regex::Error::Syntax(error.to_string()))?;
+
+    // Single-member classes come back as literals
+    let class = match hir.into_kind() {
+        HirKind::Class(class) => class,
+        HirKind::Literal(hir::Literal(bytes)) => match std::str::from_utf8(&bytes) {
+            Ok(text) => Class::Unicode(ClassUnicode::new(
+                text.chars().map(|c| ClassUnicodeRange::new(c, c)),
+            )),
+            Err(_) => Class::Bytes(ClassBytes::new(
+                bytes.iter().map(|&b| ClassBytesRange::new(b, b)),
+            )),
+        },
+        _ => {
+            // Left as written; the span is that of the original class
+            let span = positive.span();
+            return Ok(pattern[span.start.offset..span.end.offset].to_string());
+        }
+    };
+
+    let mut folded = fold_class(class, unicode_case);
+    if negated {
+        folded.negate();
+    }
+    print_class(folded)
+}
+
+/// Add the other-case forms of the members of `class`: Unicode simple case
+/// folding if `unicode_case` is set, ASCII letters only otherwise.
+fn fold_class(mut class: Class, unicode_case: bool) -> Class {
+    match &mut class {
+        Class::Unicode(class) if unicode_case => class.case_fold_simple(),
+        Class::Unicode(class) => {
+            let other_case: Vec<ClassUnicodeRange> = class
+                .iter()
+                .flat_map(|r| ascii_case_ranges(r.start() as u32, r.end() as u32))
+                .filter_map(|(start, end)| {
+                    Some(ClassUnicodeRange::new(
+                        char::from_u32(start)?,
+                        char::from_u32(end)?,
+                    ))
+                })
+                .collect();
+            class.union(&ClassUnicode::new(other_case));
+        }
+        Class::Bytes(class) => class.case_fold_simple(),
+    }
+    class
+}
+
+/// `class` as pattern text that keeps its meaning in byte mode.
+fn print_class(class: Class) -> Result<String, regex::Error> {
+    let mut printed = String::new();
+    Printer::new()
+        .print(&Hir::class(class), &mut printed)
+        .map_err(|error| regex::Error::Syntax(error.to_string()))?;
+    Ok(format!("(?u:{printed})"))
+}
+
+/// The ranges of the other-case ASCII letters within `start..=end`.
+fn ascii_case_ranges(start: u32, end: u32) -> impl Iterator<Item = (u32, u32)> {
+    [(b'a', b'z', b'A'), (b'A', b'Z', b'a')]
+        .into_iter()
+        .filter_map(move |(first, last, other_first)| {
+            let (low, high) = (start.max(first as u32), end.min(last as u32));
+            let shift = |c: u32| c - first as u32 + other_first as u32;
+            (low <= high).then(|| (shift(low), shift(high)))
+        })
+}
+
+/// Copy characters up to and including the first one in `end`.
+fn copy_through(mapped: &mut String, chars: &mut Peekable<Chars<'_>>, end: &[char]) {
+    for c in chars.by_ref() {
+        mapped.push(c);
+        if end.contains(&c) {
+            break;
+        }
+    }
+}
+
+/// Whether `c` has a special meaning in a pattern.
+fn is_meta_character(c: char) -> bool {
+    matches!(
+        c,
+        '\\' | '.'
+            | '+'
+            | '*'
+            | '?'
+            | '('
+            | ')'
+            | '|'
+            | '['
+            | ']'
+            | '{'
+            | '}'
+            | '^'
+            | '$'
+            | '#'
+            | '&'
+            | '-'
+            | '~'
+    )
+}
+
+// ============================================================================
+// Line number calculation
+// ============================================================================
+
+/// Byte range of the line containing `pos`, excluding the trailing
+/// `terminator`.
+fn line_bounds(bytes: &[u8], pos: usize, terminator: u8) -> (usize, usize) {
+    let start = memrchr(terminator, &bytes[..pos]).map_or(0, |i| i + 1);
+    let end = memchr(terminator, &bytes[pos..]).map_or(bytes.len(), |i| pos + i);
+    (start, end)
+}
+
+/// Iterate over the byte range of every line, excluding the `terminator`. A
+/// trailing terminator at the end of the buffer does not start an extra empty
+/// line.
+fn line_ranges(bytes: &[u8], terminator: u8) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut start = 0;
+
+    std::iter::from_fn(move || {
+        if start >= bytes.len() {
+            return None;
+        }
+
+        let end = memchr(terminator, &bytes[start..]).map_or(bytes.len(), |i| start + i);
+        let range = (start, end);
+        start = end + 1;
+        Some(range)
+    })
+}
+
+/// Strip a trailing `\r` left over from a CRLF line ending. Only applies when
+/// lines end in `\n`.
+fn trim_line_ending(line: &[u8], terminator: u8) -> &[u8] {
+    if terminator != b'\n' {
+        return line;
+    }
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Counts lines progressively for ascending byte positions, so each byte of
+/// the buffer is scanned at most once.
+struct LineCounter<'a> {
+    bytes: &'a [u8],
+    terminator: u8,
+    current_line: u32,
+    /// Byte offset where `current_line` starts
+    line_start: usize,
+    last_pos: usize,
+}
+
+impl<'a> LineCounter<'a> {
+    fn new(bytes: &'a [u8], terminator: u8) -> Self {
+        LineCounter {
+            bytes,
+            terminator,
+            current_line: 1,
+            line_start: 0,
+            last_pos: 0,
+        }
+    }
+
+    /// 1-based line number of `pos`. Positions must be passed in ascending order.
+    fn line_at(&mut self, pos: usize) -> u32 {
+        let skipped = &self.bytes[self.last_pos..pos];
+        let newlines = memchr_iter(self.terminator, skipped).count();
+
+        if newlines > 0 {
+            self.current_line += newlines as u32;
+            self.line_start = self.last_pos + memrchr(self.terminator, skipped).unwrap() + 1;
+        }
+
+        self.last_pos = pos;
+        self.current_line
+    }
+
+    /// Byte offset where the line of the last position passed to `line_at` starts.
+    fn line_start(&self) -> usize {
+        self.line_start
+    }
+}
+
+/// Convert match ranges to deduplicated, sorted 1-based line numbers of the
+/// lines the matches start on.
+fn ranges_to_line_numbers(bytes: &[u8], ranges: &[Range<usize>], terminator: u8) -> Vec<u32> {
+    let mut sorted_positions: Vec<usize> = ranges.iter().map(|r| r.start).collect();
+    sorted_positions.sort_unstable();
+
+    let mut line_numbers = Vec::new();
+    let mut counter = LineCounter::new(bytes, terminator);
+
+    for &pos in &sorted_positions {
+        let current_line = counter.line_at(pos);
+
+        if line_numbers.last() != Some(&current_line) {
+            line_numbers.push(current_line);
+        }
+    }
+
+    line_numbers
+}
+
+/// Convert match ranges tagged with their pattern index, from any number of
+/// patterns, into one `LineMatch` per line, keeping the smallest start on
+/// each line as its byte offset. Each line lists the ranges starting on it
+/// and the patterns they came from; identical ranges from different patterns
+/// are listed once.
+fn ranges_to_line_matches(
+    bytes: &[u8],
+    ranges: &mut [(Range<usize>, u32)],
+    terminator: u8,
+    dedup_positions: bool,
+) -> Vec<LineMatch> {
+    ranges.sort_unstable_by_key(|(r, idx)| (r.start, r.end, *idx));
+
+    let mut line_matches: Vec<LineMatch> = Vec::new();
+    let mut counter = LineCounter::new(bytes, terminator);
+
+    for (range, pattern_index) in ranges.iter() {
+        let current_line = counter.line_at(range.start);
+        let line_start = counter.line_start();
+        let match_range = match_range(bytes, range.clone(), line_start, current_line, terminator);
+
+        match line_matches.last_mut() {
+            Some(line) if line.line_number == current_line => {
+                let last = line.matches.last();
+                let duplicate = dedup_positions
+                    && last
+                        .is_some_and(|m| m.start == match_range.start && m.end == match_range.end);
+                if !duplicate {
+                    line.matches.push(match_range);
+                }
+                if !line.matched_patterns.contains(pattern_index) {
+                    line.matched_patterns.push(*pattern_index);
+                }
+            }
+            _ => line_matches.push(LineMatch::new(
+                current_line,
+                range.start as i64,
+                match_range.start,
+                vec![match_range],
+                vec![*pattern_index],
+            )),
+        }
+    }
+
+    for line in &mut line_matches {
+        line.matched_patterns.sort_unstable();
+    }
 
-            if match_positions.is_empty() {
+    line_matches
+}
+
+/// Build the `MatchRange` of the match at `range`, which starts on line
+/// `line_number` beginning at byte `line_start`. Fills in the end line and
+/// column if the match spans a line ending.
+fn match_range(
+    bytes: &[u8],
+    range: Range<usize>,
+    line_start: usize,
+    line_number: u32,
+    terminator: u8,
+) -> MatchRange {
+    let mut match_range = MatchRange {
+        start: (range.start - line_start) as u32,
+        end: (range.end - line_start) as u32,
+        end_line: None,
+        end_column: None,
+        bytes: None,
+        captures: None,
+    };
+
+    // A terminator as the last byte of the match still ends the starting line.
+    let covered = &bytes[range.start..range.end.saturating_sub(1).max(range.start)];
+    if let Some(last) = memrchr(terminator, covered) {
+        let spanned = memchr_iter(terminator, covered).count() as u32;
+        match_range.end_line = Some(line_number + spanned);
+        match_range.end_column = Some((range.end - (range.start + last + 1)) as u32);
+    }
+
+    match_range
+}
+
+/// Add `delta` to the line number of `line` and the end lines of its matches,
+/// saturating at 0 and `u32::MAX`.
+fn shift_line_numbers(line: &mut LineMatch, delta: i64) {
+    let shift =
+        |line_number: u32| (i64::from(line_number) + delta).clamp(0, i64::from(u32::MAX)) as u32;
+    line.line_number = shift(line.line_number);
+    if let Some(record_line) = &mut line.record_line {
+        *record_line = shift(*record_line);
+    }
+    for m in &mut line.matches {
+        if let Some(end_line) = &mut m.end_line {
+            *end_line = shift(*end_line);
+        }
+    }
+}
+
+// ============================================================================
+// Line text and context
+// ============================================================================
+
+/// Fill in `text`, the context lines and the `heading` of each `LineMatch` as
+/// requested by `options`. `lines` must be sorted by line number.
+fn add_line_text(
+    bytes: &[u8],
+    lines: &mut [LineMatch],
+    heading: Option<&Regex>,
+    options: &SearchOptions,
+) {
+    let include_text = options.include_text.unwrap_or(false);
+    let context_before = options.context_before.unwrap_or(0) as usize;
+    let context_after = options.context_after.unwrap_or(0) as usize;
+    let terminator = line_terminator(options);
+    let context_as_block = options.context_as_block.unwrap_or(false);
+    let max_line_length = options.max_line_length;
+
+    if matches!(options.record_mode, Some(RecordMode::Paragraph)) {
+        add_record_text(bytes, lines, options);
+    }
+    if let Some(heading) = heading {
+        add_headings(bytes, lines, heading, options);
+    }
+
+    if !include_text && context_before == 0 && context_after == 0 && !context_as_block {
+        return;
+    }
+
+    // Start of the first line not yet emitted as a match or as context, so
+    // context never repeats a line.
+    let mut emitted_end = 0;
+
+    for i in 0..lines.len() {
+        let (start, end) = line_bounds(bytes, lines[i].byte_offset as usize, terminator);
+        let next_match_start = lines.get(i + 1).map_or(bytes.len(), |next| {
+            line_bounds(bytes, next.byte_offset as usize, terminator).0
+        });
+
+        if include_text {
+            set_line_text(&mut lines[i], &bytes[start..end], terminator, options);
+        }
+
+        let mut before = Vec::new();
+        let mut line_start = start;
+        while before.len() < context_before && line_start > emitted_end {
+            let (prev_start, prev_end) = line_bounds(bytes, line_start - 1, terminator);
+            before.push(clipped_line(
+                &bytes[prev_start..prev_end],
+                terminator,
+                max_line_length,
+            ));
+            line_start = prev_start;
+        }
+        before.reverse();
+
+        emitted_end = (end + 1).min(bytes.len());
+
+        let mut after = Vec::new();
+        while after.len() < context_after && emitted_end < next_match_start {
+            let (_, next_end) = line_bounds(bytes, emitted_end, terminator);
+            after.push(clipped_line(
+                &bytes[emitted_end..next_end],
+                terminator,
+                max_line_length,
+            ));
+            emitted_end = (next_end + 1).min(bytes.len());
+        }
+
+        let line = &mut lines[i];
+        if context_as_block {
+            let block_line = before.len();
+            let mut block = before;
+            block.push(clipped_line(
+                &bytes[start..end],
+                terminator,
+                max_line_length,
+            ));
+            block.extend(after);
+            let (texts, base64) = decode_lines(&block, options);
+            line.block_line = Some(block_line as u32);
+            line.block = Some(texts.join("\n"));
+            line.context_base64 = base64.then_some(true);
+        } else if context_before > 0 || context_after > 0 {
+            let before_len = before.len();
+            let mut context = before;
+            context.extend(after);
+            let (mut texts, base64) = decode_lines(&context, options);
+            let after = texts.split_off(before_len);
+            line.context_before = (context_before > 0).then_some(texts);
+            line.context_after = (context_after > 0).then_some(after);
+            line.context_base64 = base64.then_some(true);
+        }
+    }
+}
+
+/// Fill in `heading` of each `LineMatch` with the nearest line above it that
+/// matches `heading`. `lines` must be sorted by line number. Each line is only
+/// scanned back to the previous one, whose heading carries over if none is
+/// found in between.
+fn add_headings(bytes: &[u8], lines: &mut [LineMatch], heading: &Regex, options: &SearchOptions) {
+    let terminator = line_terminator(options);
+    // Start of the line above which every line has been scanned
+    let mut scanned_to = 0;
+    let mut current = None;
+
+    for line in lines {
+        let start = line_bounds(bytes, line.byte_offset as usize, terminator).0;
+        let mut line_start = start;
+        while line_start > scanned_to {
+            let (prev_start, prev_end) = line_bounds(bytes, line_start - 1, terminator);
+            if heading.is_match(trim_line_ending(&bytes[prev_start..prev_end], terminator)) {
+                current = Some(line_text(
+                    &bytes[prev_start..prev_end],
+                    terminator,
+                    options.max_line_length,
+                ));
+                break;
+            }
+            line_start = prev_start;
+        }
+        scanned_to = start;
+        line.heading = current.clone();
+    }
+}
+
+/// With `options.context_as_lines`, move the context lines of each
+/// `LineMatch` into entries of their own around it. The context of a line is
+/// the lines right before and after it, so their positions are found by
+/// walking from the line itself.
+fn flatten_context(bytes: &[u8], lines: Vec<LineMatch>, options: &SearchOptions) -> Vec<LineMatch> {
+    if !options.context_as_lines.unwrap_or(false) || options.context_as_block.unwrap_or(false) {
+        return lines;
+    }
+
+    let terminator = line_terminator(options);
+    let context_line = |line_number: u32, line_start: usize, text: String, base64| LineMatch {
+        text: Some(text),
+        is_match: Some(false),
+        text_base64: base64,
+        ..LineMatch::new(line_number, line_start as i64, 0, Vec::new(), Vec::new())
+    };
+
+    let mut flat = Vec::with_capacity(lines.len());
+    for mut line in lines {
+        let (start, end) = line_bounds(bytes, line.byte_offset as usize, terminator);
+        let before = line.context_before.take().unwrap_or_default();
+        let after = line.context_after.take().unwrap_or_default();
+        let base64 = line.context_base64.take();
+
+        let mut starts = Vec::with_capacity(before.len());
+        let mut line_start = start;
+        for _ in 0..before.len() {
+            line_start = line_bounds(bytes, line_start - 1, terminator).0;
+            starts.push(line_start);
+        }
+        let first_number = line.line_number - before.len() as u32;
+        for ((text, line_start), line_number) in before
+            .into_iter()
+            .zip(starts.into_iter().rev())
+            .zip(first_number..)
+        {
+            flat.push(context_line(line_number, line_start, text, base64));
+        }
+
+        let line_number = line.line_number;
+        line.is_match = Some(true);
+        flat.push(line);
+
+        let mut line_start = end + 1;
+        for (text, line_number) in after.into_iter().zip(line_number + 1..) {
+            flat.push(context_line(line_number, line_start, text, base64));
+            line_start = line_bounds(bytes, line_start, terminator).1 + 1;
+        }
+    }
+    flat
+}
+
+/// Fill in `record_line` and `record_text` of each `LineMatch` with the
+/// paragraph containing it. `lines` must be sorted by line number; the text of
+/// each paragraph is only set on its first line.
+fn add_record_text(bytes: &[u8], lines: &mut [LineMatch], options: &SearchOptions) {
+    let terminator = line_terminator(options);
+    let is_blank =
+        |start: usize, end: usize| trim_line_ending(&bytes[start..end], terminator).is_empty();
+    // Byte range and first line number of the previous line's paragraph
+    let mut record: Option<(Range<usize>, u32)> = None;
+
+    for line in lines {
+        let line_start = line.line_start();
+
+        if let Some((range, record_line)) = &record {
+            if range.contains(&line_start) {
+                line.record_line = Some(*record_line);
                 continue;
             }
+        }
+
+        let (mut start, mut end) = line_bounds(bytes, line_start, terminator);
+        let mut record_line = line.line_number;
+        while start > 0 {
+            let (prev_start, prev_end) = line_bounds(bytes, start - 1, terminator);
+            if is_blank(prev_start, prev_end) {
+                break;
+            }
+            start = prev_start;
+            record_line -= 1;
+        }
+        while end < bytes.len() {
+            let (_, next_end) = line_bounds(bytes, end + 1, terminator);
+            if is_blank(end + 1, next_end) {
+                break;
+            }
+            end = next_end;
+        }
+
+        let text = trim_line_ending(&bytes[start..end], terminator);
+        let range = match options.max_line_length {
+            Some(max_len) => {
+                let focus_start = line_start - start + line.column as usize;
+                clip_range(text, max_len as usize, focus_start..focus_start)
+            }
+            None => 0..text.len(),
+        };
+
+        line.record_line = Some(record_line);
+        if let Some((text, base64)) = decode_text(&text[range], options) {
+            line.record_text = Some(text);
+            line.record_text_base64 = base64.then_some(true);
+        }
+        record = Some((start..end + 1, record_line));
+    }
+}
 
-            let frequency = match_positions.len() as u32;
-            let line_numbers = positions_to_line_numbers(bytes, &match_positions);
+/// Drop the strings of the lines, in order, from the first one whose strings
+/// do not fit into `options.max_total_text_bytes`. Returns whether any string
+/// was dropped, or `None` without a budget.
+fn limit_total_text(lines: &mut [LineMatch], options: &SearchOptions) -> Option<bool> {
+    let mut budget = options.max_total_text_bytes? as usize;
+    let mut truncated = false;
 
-            pattern_matches.push(PatternMatch {
-                pattern_index: idx as u32,
-                frequency,
-                line_numbers,
-            });
+    for line in lines.iter_mut() {
+        let size = line_text_bytes(line);
+        if truncated || size > budget {
+            truncated = true;
+            clear_line_text(line);
+        } else {
+            budget -= size;
         }
+    }
+    Some(truncated)
+}
+
+/// Total length of the strings returned for `line`.
+fn line_text_bytes(line: &LineMatch) -> usize {
+    let lines_len =
+        |lines: &Option<Vec<String>>| lines.iter().flatten().map(String::len).sum::<usize>();
+    let match_len = |m: &MatchRange| {
+        m.bytes.as_ref().map_or(0, String::len)
+            + m.captures
+                .iter()
+                .flatten()
+                .map(|(name, text)| name.len() + text.len())
+                .sum::<usize>()
+    };
+    [
+        &line.text,
+        &line.replaced_text,
+        &line.record_text,
+        &line.block,
+        &line.heading,
+    ]
+    .into_iter()
+    .map(|text| text.as_ref().map_or(0, String::len))
+    .sum::<usize>()
+        + lines_len(&line.context_before)
+        + lines_len(&line.context_after)
+        + line.matches.iter().map(match_len).sum::<usize>()
+}
+
+/// Empty `text` of `line` and drop its other strings.
+fn clear_line_text(line: &mut LineMatch) {
+    if let Some(text) = &mut line.text {
+        text.clear();
+    }
+    line.text_base64 = None;
+    line.replaced_text = None;
+    line.record_text = None;
+    line.record_text_base64 = None;
+    line.context_before = None;
+    line.context_after = None;
+    line.block = None;
+    line.block_line = None;
+    line.context_base64 = None;
+    line.heading = None;
+    for m in &mut line.matches {
+        m.bytes = None;
+        m.captures = None;
+    }
+}
+
+/// Set `text` of `line` from the line's bytes: a snippet around the first
+/// match if `options.snippet_radius` is set, otherwise the line limited to
+/// `options.max_line_length` bytes around the first match if a limit is given.
+fn set_line_text(line: &mut LineMatch, line_bytes: &[u8], terminator: u8, options: &SearchOptions) {
+    let text = trim_line_ending(line_bytes, terminator);
+    if options.include_indent.unwrap_or(false) {
+        let indent = text
+            .iter()
+            .take_while(|&&b| b == b' ' || b == b'\t')
+            .count();
+        line.indent = Some(indent as u32);
+    }
 
-        if !pattern_matches.is_empty() {
-            let total_lines = memchr_iter(b'\n', bytes).count() as u32 + 1;
-            results.push(FilePatternMatches {
-                file_path: file_path.clone(),
-                total_lines,
-                patterns: pattern_matches,
-            });
+    let focus = line
+        .matches
+        .first()
+        .map_or(0..0, |m| m.start as usize..m.end as usize);
+
+    let range = match (options.snippet_radius, options.max_line_length) {
+        (Some(radius), _) => snippet_range(text, radius as usize, focus),
+        (None, Some(max_len)) => clip_range(text, max_len as usize, focus),
+        (None, None) => {
+            set_decoded_text(line, text, options);
+            return;
         }
+    };
+
+    line.text_offset = Some(range.start as u32);
+    line.truncated = Some(range.len() < text.len());
+    set_decoded_text(line, &text[range], options);
+}
+
+/// Set `text` of `line` to `text` decoded as UTF-8, handling invalid UTF-8 as
+/// `options.lossy_mode` says.
+fn set_decoded_text(line: &mut LineMatch, text: &[u8], options: &SearchOptions) {
+    if let Some((text, base64)) = decode_text(text, options) {
+        line.text = Some(text);
+        line.text_base64 = base64.then_some(true);
     }
+}
 
-    results
+/// Decode `text` as UTF-8, handling invalid UTF-8 as `options.lossy_mode`
+/// says. Returns the text and whether it is base64-encoded, or `None` if it
+/// is left out.
+fn decode_text(text: &[u8], options: &SearchOptions) -> Option<(String, bool)> {
+    if let Ok(text) = std::str::from_utf8(text) {
+        return Some((text.to_string(), false));
+    }
+
+    match options.lossy_mode.unwrap_or(LossyMode::Lossy) {
+        LossyMode::Lossy => Some((String::from_utf8_lossy(text).into_owned(), false)),
+        LossyMode::Skip => None,
+        LossyMode::Base64 => Some((BASE64_STANDARD.encode(text), true)),
+    }
 }
 
-// ============================================================================
-// Line number calculation
-// ============================================================================
+/// Decode a group of lines that are returned together, such as the context
+/// of a match, as `options.lossy_mode` says. With `LossyMode.Base64`, all the
+/// lines are base64-encoded if any of them is not valid UTF-8, so the caller
+/// only needs one flag; with `LossyMode.Skip`, such lines are left empty so
+/// the others keep their positions. Returns the lines and whether they are
+/// base64-encoded.
+fn decode_lines(lines: &[&[u8]], options: &SearchOptions) -> (Vec<String>, bool) {
+    let base64 = matches!(options.lossy_mode, Some(LossyMode::Base64))
+        && lines.iter().any(|line| std::str::from_utf8(line).is_err());
+    let texts = lines
+        .iter()
+        .map(|line| {
+            if base64 {
+                BASE64_STANDARD.encode(line)
+            } else {
+                decode_text(line, options).map_or_else(String::new, |(text, _)| text)
+            }
+        })
+        .collect();
+    (texts, base64)
+}
 
-/// Convert byte positions to deduplicated, sorted 1-based line numbers.
-fn positions_to_line_numbers(bytes: &[u8], positions: &[usize]) -> Vec<u32> {
-    let mut sorted_positions = positions.to_vec();
-    sorted_positions.sort_unstable();
+/// Byte range of `line` to return when limited to `max_len` bytes: the whole
+/// line if it fits, otherwise `max_len` bytes from the line start, or from the
+/// start of `focus` if `focus` would not fit. Never splits a UTF-8 character.
+fn clip_range(line: &[u8], max_len: usize, focus: Range<usize>) -> Range<usize> {
+    if line.len() <= max_len {
+        return 0..line.len();
+    }
 
-    let mut line_numbers = Vec::new();
-    let mut current_line: u32 = 1;
-    let mut last_pos: usize = 0;
+    let is_continuation = |i: usize| line.get(i).is_some_and(|&b| b & 0xC0 == 0x80);
 
-    for &pos in &sorted_positions {
-        current_line += memchr_iter(b'\n', &bytes[last_pos..pos]).count() as u32;
-        last_pos = pos;
+    let mut start = if focus.end <= max_len {
+        0
+    } else {
+        focus.start.min(line.len())
+    };
+    while start > 0 && is_continuation(start) {
+        start -= 1;
+    }
 
-        if line_numbers.last() != Some(&current_line) {
-            line_numbers.push(current_line);
+    let mut end = (start + max_len).min(line.len());
+    while end > start && is_continuation(end) {
+        end -= 1;
+    }
+
+    start..end
+}
+
+/// Byte range of `line` covering `focus` plus up to `radius` bytes on each
+/// side. The padding is shrunk rather than split a UTF-8 character.
+fn snippet_range(line: &[u8], radius: usize, focus: Range<usize>) -> Range<usize> {
+    let focus_start = focus.start.min(line.len());
+    let focus_end = focus.end.clamp(focus_start, line.len());
+    let is_continuation = |i: usize| line.get(i).is_some_and(|&b| b & 0xC0 == 0x80);
+
+    let mut start = focus_start.saturating_sub(radius);
+    while start < focus_start && is_continuation(start) {
+        start += 1;
+    }
+
+    let mut end = focus_end.saturating_add(radius).min(line.len());
+    while end > focus_end && is_continuation(end) {
+        end -= 1;
+    }
+
+    start..end
+}
+
+/// Fill in `bytes` of each match of `line` with the matched bytes, base64
+/// encoded. `line_bytes` starts at the start of the line and extends at least
+/// to the end of its last match. Must run before columns are converted.
+fn add_match_bytes(line: &mut LineMatch, line_bytes: &[u8]) {
+    for m in &mut line.matches {
+        let matched = &line_bytes[m.start as usize..m.end as usize];
+        m.bytes = Some(BASE64_STANDARD.encode(matched));
+    }
+}
+
+/// Add the optional details requested by `options` to `line`, a line matched
+/// on its own. `line_bytes` is the line without its terminator and `text` the
+/// same with a trailing `\r` removed, which the columns of `line` refer to.
+fn add_line_details(
+    line: &mut LineMatch,
+    line_bytes: &[u8],
+    text: &[u8],
+    regexes: &[Regex],
+    options: &SearchOptions,
+) {
+    if options.include_text.unwrap_or(false) {
+        set_line_text(line, line_bytes, line_terminator(options), options);
+    }
+    if options.include_match_bytes.unwrap_or(false) {
+        add_match_bytes(line, line_bytes);
+    }
+    if options.captures.unwrap_or(false) {
+        add_captures(line, text, 0, regexes);
+    }
+    convert_columns(line, line_bytes, options);
+}
+
+/// Fill in `captures` of each match of `line` with the named groups of the
+/// first regex with named groups that finds exactly that match. `haystack`
+/// is the searched content and `line_start` the offset of the line in it, so
+/// look-around assertions see the same context as the search. Must run before
+/// columns are converted.
+fn add_captures(line: &mut LineMatch, haystack: &[u8], line_start: usize, regexes: &[Regex]) {
+    for m in &mut line.matches {
+        let start = line_start + m.start as usize;
+        let end = line_start + m.end as usize;
+
+        m.captures = regexes.iter().find_map(|regex| {
+            regex.capture_names().flatten().next()?;
+            let caps = regex.captures_at(haystack, start)?;
+            let whole = caps.get(0)?;
+            if whole.start() != start || whole.end() != end {
+                return None;
+            }
+
+            let names: BTreeMap<String, String> = regex
+                .capture_names()
+                .zip(caps.iter())
+                .filter_map(|(name, group)| {
+                    let text = String::from_utf8_lossy(group?.as_bytes());
+                    Some((name?.to_string(), text.into_owned()))
+                })
+                .collect();
+            (!names.is_empty()).then_some(names)
+        });
+    }
+}
+
+/// Convert the byte columns of `line` to `options.column_unit`, expanding
+/// tabs to `options.tab_width`. `line_bytes` starts at the start of the line
+/// and extends at least to the end of its last match.
+fn convert_columns(line: &mut LineMatch, line_bytes: &[u8], options: &SearchOptions) {
+    let unit = options.column_unit.unwrap_or(ColumnUnit::Byte);
+    let tab_width = options.tab_width.filter(|&width| width > 0);
+    if matches!(unit, ColumnUnit::Byte) && tab_width.is_none() {
+        return;
+    }
+
+    // Every range starts at a line start, so tab stops line up with it.
+    let width = |range: Range<usize>| -> u32 {
+        let text = String::from_utf8_lossy(&line_bytes[range]);
+        let mut column = 0;
+        for c in text.chars() {
+            column += match (c, tab_width) {
+                ('\t', Some(tab_width)) => tab_width - column % tab_width,
+                _ => match unit {
+                    ColumnUnit::Byte => c.len_utf8() as u32,
+                    ColumnUnit::Char => 1,
+                    ColumnUnit::Utf16 => c.len_utf16() as u32,
+                },
+            };
+        }
+        column
+    };
+
+    for m in &mut line.matches {
+        let (start, end) = (m.start as usize, m.end as usize);
+        if let Some(end_column) = &mut m.end_column {
+            *end_column = width(end - *end_column as usize..end);
         }
+        m.start = width(0..start);
+        m.end = width(0..end);
     }
+    line.column = width(0..line.column as usize);
+    if let Some(text_offset) = &mut line.text_offset {
+        *text_offset = width(0..*text_offset as usize);
+    }
+}
 
-    line_numbers
+/// Fill in `replaced_text` of each `LineMatch` by replacing every match of
+/// `regex` in its line with `replacement`. Context lines returned as their
+/// own entries are left alone.
+fn add_replaced_text(
+    bytes: &[u8],
+    lines: &mut [LineMatch],
+    regex: &Regex,
+    replacement: &str,
+    terminator: u8,
+) {
+    for line in lines.iter_mut().filter(|line| line.is_match != Some(false)) {
+        let start = line.line_start();
+        let (_, end) = line_bounds(bytes, start, terminator);
+        let text = trim_line_ending(&bytes[start..end], terminator);
+        let replaced = regex.replace_all(text, replacement.as_bytes());
+        line.replaced_text = Some(String::from_utf8_lossy(&replaced).into_owned());
+    }
+}
+
+/// Decode a line's bytes for output, dropping a trailing `\r` from CRLF line
+/// endings and keeping at most `max_len` bytes from the start of the line.
+fn line_text(line: &[u8], terminator: u8, max_len: Option<u32>) -> String {
+    String::from_utf8_lossy(clipped_line(line, terminator, max_len)).into_owned()
+}
+
+/// A line's bytes without a trailing `\r` from CRLF line endings, keeping at
+/// most `max_len` bytes from the start of the line.
+fn clipped_line(line: &[u8], terminator: u8, max_len: Option<u32>) -> &[u8] {
+    let line = trim_line_ending(line, terminator);
+    let range = match max_len {
+        Some(max_len) => clip_range(line, max_len as usize, 0..0),
+        None => 0..line.len(),
+    };
+    &line[range]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"a foo\nb bar\nc foo bar\nd\ne bar\nf foo\n";
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn searched_lines(patterns: &[String], mode: MatchMode, options: &SearchOptions) -> Vec<u32> {
+        let compiled = compile_search(patterns, false, false, options).unwrap();
+        let options = SearchOptions {
+            include_lines: Some(true),
+            ..options.clone()
+        };
+        search_bytes("", TEXT, &compiled, mode, &options, None)
+            .and_then(|result| result.lines)
+            .map_or_else(Vec::new, |lines| {
+                lines.iter().map(|l| l.line_number).collect()
+            })
+    }
+
+    fn regex(
+        pattern: &str,
+        unicode: bool,
+        case_insensitive: bool,
+        options: &SearchOptions,
+    ) -> Regex {
+        compile_search(&patterns(&[pattern]), unicode, case_insensitive, options)
+            .unwrap()
+            .regexes
+            .remove(0)
+    }
+
+    #[test]
+    fn matching_lines_follow_search_selection() {
+        let cases = [
+            (patterns(&["foo"]), MatchMode::Or, SearchOptions::default()),
+            (
+                patterns(&["foo"]),
+                MatchMode::Or,
+                SearchOptions {
+                    invert_match: Some(true),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "bar"]),
+                MatchMode::And,
+                SearchOptions {
+                    invert_match: Some(true),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "bar"]),
+                MatchMode::And,
+                SearchOptions {
+                    within_lines: Some(0),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "^e"]),
+                MatchMode::And,
+                SearchOptions {
+                    within_lines: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "nowhere"]),
+                MatchMode::And,
+                SearchOptions::default(),
+            ),
+        ];
+
+        for (case, (patterns, mode, options)) in cases.into_iter().enumerate() {
+            let compiled = compile_search(&patterns, false, false, &options).unwrap();
+            let expected = searched_lines(&patterns, mode, &options);
+            let lines = line_numbers_in_bytes(TEXT, &compiled, mode, &options, None);
+            assert_eq!(lines, expected, "case {case}");
+            let count = count_lines_in_bytes(TEXT, &compiled, mode, &options, None);
+            assert_eq!(count as usize, expected.len(), "case {case}");
+        }
+
+        let options = SearchOptions {
+            invert_match: Some(true),
+            ..Default::default()
+        };
+        let compiled = compile_search(&patterns(&["foo"]), false, false, &options).unwrap();
+        let lines = line_numbers_in_bytes(
+            b"a foo\nb bar\nc foo bar\nd\n",
+            &compiled,
+            MatchMode::Or,
+            &options,
+            None,
+        );
+        assert_eq!(lines, [2, 4]);
+    }
+
+    #[test]
+    fn summary_counts_like_search() {
+        let cases = [
+            (
+                patterns(&["foo", "bar"]),
+                MatchMode::Or,
+                SearchOptions::default(),
+            ),
+            (
+                patterns(&["foo", "bar"]),
+                MatchMode::And,
+                SearchOptions::default(),
+            ),
+            (
+                patterns(&["o", "fo"]),
+                MatchMode::Or,
+                SearchOptions {
+                    dedup_positions: Some(false),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["o", "bar"]),
+                MatchMode::Or,
+                SearchOptions {
+                    max_results: Some(2),
+                    max_matches_per_line: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "bar"]),
+                MatchMode::And,
+                SearchOptions {
+                    within_lines: Some(1),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo"]),
+                MatchMode::Or,
+                SearchOptions {
+                    invert_match: Some(true),
+                    ..Default::default()
+                },
+            ),
+            (
+                patterns(&["foo", "nowhere"]),
+                MatchMode::And,
+                SearchOptions::default(),
+            ),
+        ];
+
+        for (case, (patterns, mode, options)) in cases.into_iter().enumerate() {
+            let compiled = compile_search(&patterns, false, false, &options).unwrap();
+            let counts = |result: Option<FilePatternMatches>| {
+                result.map(|r| {
+                    let frequencies: Vec<(u32, u32)> = r
+                        .patterns
+                        .iter()
+                        .map(|p| (p.pattern_index, p.frequency))
+                        .collect();
+                    (
+                        r.matching_lines,
+                        r.total_matches,
+                        r.total_lines,
+                        r.truncated,
+                        frequencies,
+                    )
+                })
+            };
+            let summary = summarize_bytes("", TEXT, &compiled, mode, &options, None);
+            assert!(summary
+                .iter()
+                .flat_map(|r| &r.patterns)
+                .all(|p| p.line_numbers.is_empty()));
+            let search = search_bytes("", TEXT, &compiled, mode, &options, None);
+            assert_eq!(counts(summary), counts(search), "case {case}");
+        }
+
+        // AND collects the longest pattern first but reports input order
+        let options = SearchOptions::default();
+        let compiled =
+            compile_search(&patterns(&["o", "foo bar"]), false, false, &options).unwrap();
+        let result = search_bytes("", TEXT, &compiled, MatchMode::And, &options, None).unwrap();
+        let indices: Vec<u32> = result.patterns.iter().map(|p| p.pattern_index).collect();
+        assert_eq!(indices, [0, 1]);
+    }
+
+    #[test]
+    fn stream_dedups_like_search() {
+        for dedup_positions in [true, false] {
+            let options = SearchOptions {
+                include_lines: Some(true),
+                dedup_positions: Some(dedup_positions),
+                ..Default::default()
+            };
+            let compiled =
+                compile_search(&patterns(&["foo", "foo b", "fo+"]), false, false, &options)
+                    .unwrap();
+            let ranges = |line: &LineMatch| -> Vec<(u32, u32)> {
+                line.matches.iter().map(|m| (m.start, m.end)).collect()
+            };
+
+            let mut streamed = Vec::new();
+            stream_bytes::<()>(TEXT, &compiled, MatchMode::Or, &options, None, |line| {
+                streamed.push(ranges(&line));
+                Ok(true)
+            })
+            .unwrap();
+            let result = search_bytes("", TEXT, &compiled, MatchMode::Or, &options, None).unwrap();
+            let searched: Vec<Vec<(u32, u32)>> =
+                result.lines.iter().flatten().map(ranges).collect();
+            assert_eq!(streamed, searched, "dedup_positions {dedup_positions}");
+        }
+    }
+
+    #[test]
+    fn replacement_skips_context_entries() {
+        let options = SearchOptions {
+            include_lines: Some(true),
+            context_before: Some(1),
+            context_as_lines: Some(true),
+            replacement: Some("baz".to_string()),
+            ..Default::default()
+        };
+        let compiled = compile_search(&patterns(&["bar"]), false, false, &options).unwrap();
+        let result = search_bytes("", TEXT, &compiled, MatchMode::Or, &options, None).unwrap();
+        let replaced: Vec<(u32, Option<&str>)> = result
+            .lines
+            .as_deref()
+            .unwrap()
+            .iter()
+            .map(|line| (line.line_number, line.replaced_text.as_deref()))
+            .collect();
+        assert_eq!(
+            replaced,
+            [
+                (1, None),
+                (2, Some("b baz")),
+                (3, Some("c foo baz")),
+                (4, None),
+                (5, Some("e baz")),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_budget_covers_context_lines() {
+        let search = |budget| {
+            let options = SearchOptions {
+                include_lines: Some(true),
+                include_text: Some(true),
+                context_before: Some(1),
+                max_total_text_bytes: Some(budget),
+                ..Default::default()
+            };
+            let compiled = compile_search(&patterns(&["foo"]), false, false, &options).unwrap();
+            search_bytes("", TEXT, &compiled, MatchMode::Or, &options, None).unwrap()
+        };
+        let texts = |result: &FilePatternMatches| -> Vec<(String, Option<Vec<String>>)> {
+            result
+                .lines
+                .iter()
+                .flatten()
+                .map(|line| (line.text.clone().unwrap(), line.context_before.clone()))
+                .collect()
+        };
+        let context = |line: &str| Some(vec![line.to_string()]);
+
+        // "a foo" takes 5 bytes, "c foo bar" with "b bar" 14, "f foo" with "e bar" 10
+        let result = search(19);
+        assert_eq!(result.text_truncated, Some(true));
+        assert_eq!(
+            texts(&result),
+            [
+                ("a foo".to_string(), Some(Vec::new())),
+                ("c foo bar".to_string(), context("b bar")),
+                (String::new(), None),
+            ]
+        );
+
+        // Later lines stay empty even where they would fit again
+        let result = search(18);
+        assert_eq!(
+            texts(&result),
+            [
+                ("a foo".to_string(), Some(Vec::new())),
+                (String::new(), None),
+                (String::new(), None),
+            ]
+        );
+
+        assert_eq!(search(29).text_truncated, Some(false));
+    }
+
+    #[test]
+    fn byte_range_is_checked_and_clamped() {
+        let options = SearchOptions {
+            start_byte: Some(-1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_byte_range(&options),
+            Err(SearchError::NegativeByteRange("startByte", -1))
+        ));
+        let options = SearchOptions {
+            start_byte: Some(6),
+            byte_length: Some(i64::MAX),
+            ..Default::default()
+        };
+        assert!(check_byte_range(&options).is_ok());
+        let window = LineWindow::new(TEXT, &options);
+        assert_eq!(window.region, 6..TEXT.len());
+
+        let options = SearchOptions {
+            base_line: Some(u32::MAX),
+            start_line: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_byte_range(&options),
+            Err(SearchError::LineNumberOverflow(line)) if line == u64::from(u32::MAX) + 1
+        ));
+        let options = SearchOptions {
+            base_line: Some(u32::MAX - 2),
+            start_line: Some(2),
+            ..Default::default()
+        };
+        assert!(check_byte_range(&options).is_ok());
+        let lines = searched_lines(&patterns(&["foo"]), MatchMode::Or, &options);
+        assert_eq!(lines, [u32::MAX, u32::MAX]);
+    }
+
+    /// A fresh, empty directory for the files of one test.
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("runo-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn atomic_write_uses_unique_temp_files() {
+        let dir = test_dir("atomic-write");
+        let path = dir.join("file.txt");
+        std::fs::write(&path, "old").unwrap();
+        let file_path = path.to_str().unwrap();
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                scope.spawn(move || write_file_atomic(file_path, format!("new {i}").as_bytes()));
+            }
+        });
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("new "), "{content}");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.txt");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            write_file_atomic(link.to_str().unwrap(), b"through link").unwrap();
+            assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+            assert_eq!(std::fs::read(&path).unwrap(), b"through link");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn offset_to_line_boundaries() {
+        let dir = test_dir("offset-to-line");
+        let path = dir.join("file.txt");
+        std::fs::write(&path, "ab\ncd\ref").unwrap();
+        let file_path = || path.to_str().unwrap().to_string();
+        let line = |offset, options| offset_to_line(file_path(), offset, options).ok();
+
+        assert_eq!(line(0, None), Some(1));
+        assert_eq!(line(2, None), Some(1));
+        assert_eq!(line(3, None), Some(2));
+        assert_eq!(line(8, None), Some(2));
+        assert_eq!(line(9, None), None);
+        assert_eq!(line(-1, None), None);
+
+        let cr = || {
+            Some(SearchOptions {
+                line_terminator: Some(b'\r'),
+                ..Default::default()
+            })
+        };
+        assert_eq!(line(5, cr()), Some(1));
+        assert_eq!(line(6, cr()), Some(2));
+        assert_eq!(line(8, cr()), Some(2));
+        let zero_based = SearchOptions {
+            zero_based: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(line(0, Some(zero_based)), Some(0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pattern_set_keeps_its_line_terminator() {
+        let dir = test_dir("pattern-set");
+        let path = dir.join("file.txt");
+        std::fs::write(&path, "a foo\rb bar\rc foo\r").unwrap();
+        let options = SearchOptions {
+            line_terminator: Some(b'\r'),
+            ..Default::default()
+        };
+        let set = compile_patterns(patterns(&["^.*foo$"]), false, false, Some(options)).unwrap();
+        let options = SearchOptions {
+            include_lines: Some(true),
+            ..Default::default()
+        };
+        let result = search_with_set(
+            &set,
+            path.to_str().unwrap().to_string(),
+            MatchMode::Or,
+            Some(options),
+        );
+        let lines: Vec<u32> = result[0]
+            .lines
+            .iter()
+            .flatten()
+            .map(|line| line.line_number)
+            .collect();
+        assert_eq!(lines, [1, 3]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lossy_mode_applies_to_every_line_text() {
+        let text = b"foo 1\nfoo \xFF\nbar\nfoo 2\n\xFE\n";
+        let search = |options: SearchOptions| {
+            let compiled = compile_search(&patterns(&["foo"]), false, false, &options).unwrap();
+            let options = SearchOptions {
+                include_lines: Some(true),
+                include_text: Some(true),
+                ..options
+            };
+            search_bytes("", text, &compiled, MatchMode::Or, &options, None).unwrap()
+        };
+
+        let skipped = search(SearchOptions {
+            lossy_mode: Some(LossyMode::Skip),
+            context_after: Some(1),
+            ..Default::default()
+        });
+        let lines = skipped.lines.unwrap();
+        let numbers: Vec<u32> = lines.iter().map(|l| l.line_number).collect();
+        assert_eq!(numbers, [1, 4]);
+        assert_eq!(skipped.total_matches, 2);
+        assert_eq!(skipped.matching_lines, 2);
+        assert_eq!(skipped.patterns[0].frequency, 2);
+        assert_eq!(lines[0].context_after, Some(vec![String::new()]));
+        assert_eq!(lines[1].context_after, Some(vec![String::new()]));
+
+        let encoded = search(SearchOptions {
+            lossy_mode: Some(LossyMode::Base64),
+            context_after: Some(1),
+            ..Default::default()
+        });
+        let lines = encoded.lines.unwrap();
+        assert_eq!(lines[1].text.as_deref(), Some("Zm9vIP8="));
+        assert_eq!(lines[1].text_base64, Some(true));
+        assert_eq!(lines[1].context_after, Some(vec!["bar".to_string()]));
+        assert_eq!(lines[1].context_base64, None);
+        assert_eq!(lines[2].context_after, Some(vec!["/g==".to_string()]));
+        assert_eq!(lines[2].context_base64, Some(true));
+
+        let blocks = search(SearchOptions {
+            lossy_mode: Some(LossyMode::Base64),
+            context_after: Some(1),
+            context_as_block: Some(true),
+            ..Default::default()
+        });
+        let lines = blocks.lines.unwrap();
+        assert_eq!(lines[2].block.as_deref(), Some("Zm9vIDI=\n/g=="));
+        assert_eq!(lines[2].context_base64, Some(true));
+
+        let records = search(SearchOptions {
+            lossy_mode: Some(LossyMode::Base64),
+            record_mode: Some(RecordMode::Paragraph),
+            ..Default::default()
+        });
+        let lines = records.lines.unwrap();
+        let record = BASE64_STANDARD.encode(&text[..text.len() - 1]);
+        assert_eq!(lines[0].record_text, Some(record));
+        assert_eq!(lines[0].record_text_base64, Some(true));
+    }
+
+    #[test]
+    fn cache_keys_differ_for_compile_settings() {
+        let key = |list: &[&str], unicode, case_insensitive, options: SearchOptions| {
+            build_cache_key(&patterns(list), unicode, case_insensitive, &options).unwrap()
+        };
+        let with = |options: SearchOptions| key(&["a"], false, false, options);
+        let keys = [
+            with(SearchOptions::default()),
+            key(&["a"], true, false, SearchOptions::default()),
+            key(&["a"], false, true, SearchOptions::default()),
+            key(&["b"], false, false, SearchOptions::default()),
+            key(&["a", "b"], false, false, SearchOptions::default()),
+            key(&["a\0b"], false, false, SearchOptions::default()),
+            with(SearchOptions {
+                unicode_dot: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                unicode_case: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                smart_case: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                whole_word: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                literal: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                full_line_match: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                line_prefix: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                anchors: Some(vec![Anchor::LineStart]),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                multi_line: Some(false),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                dot_all: Some(true),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                normalize: Some(Normalization::Nfc),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                byte_map: Some((0..=255).rev().collect()),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                size_limit: Some(1 << 20),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                dfa_size_limit: Some(1 << 20),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                line_terminator: Some(0),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                exclude_patterns: Some(vec!["b".to_string()]),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                exclude_patterns: Some(vec!["b".to_string(), "c".to_string()]),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                exclude_patterns: Some(vec!["b\0c".to_string()]),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                guard: Some("b".to_string()),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                scope: Some("b".to_string()),
+                ..Default::default()
+            }),
+            with(SearchOptions {
+                heading_pattern: Some("b".to_string()),
+                ..Default::default()
+            }),
+        ];
+
+        for (i, first) in keys.iter().enumerate() {
+            for (j, second) in keys.iter().enumerate().skip(i + 1) {
+                assert_ne!(first, second, "keys {i} and {j}");
+            }
+        }
+
+        // Result-shaping options leave the key alone
+        let shaped = with(SearchOptions {
+            include_lines: Some(true),
+            max_results: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(shaped, keys[0]);
+
+        // Flags resolve to the settings they turn on
+        let flagged = with(SearchOptions {
+            flags: Some("x".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(flagged, keys[11]);
+    }
+
+    #[test]
+    fn flags_only_turn_settings_on() {
+        let flags = |flags: &str, options: SearchOptions| {
+            let options = SearchOptions {
+                flags: Some(flags.to_string()),
+                ..options
+            };
+            RegexFlags::new(false, false, &options)
+        };
+
+        let parsed = flags("iSwxFs", SearchOptions::default()).unwrap();
+        assert!(parsed.case_insensitive && parsed.smart_case && parsed.whole_word);
+        assert!(parsed.full_line && parsed.literal && parsed.dot_all);
+        let repeated = flags("ii", SearchOptions::default()).unwrap();
+        assert!(repeated.case_insensitive && !repeated.smart_case);
+        let options = SearchOptions {
+            whole_word: Some(true),
+            ..Default::default()
+        };
+        assert!(flags("", options).unwrap().whole_word);
+        assert!(matches!(
+            flags("iq", SearchOptions::default()),
+            Err(SearchError::InvalidFlag('q'))
+        ));
+
+        // `S` decides the case from the pattern even with `i`
+        let options = SearchOptions {
+            flags: Some("iS".to_string()),
+            ..Default::default()
+        };
+        assert_matches(&regex("abc", false, false, &options), &["ABC"], &[]);
+        assert_matches(&regex("Abc", false, false, &options), &["Abc"], &["abc"]);
+
+        // `x` wins over a line prefix
+        let options = SearchOptions {
+            flags: Some("x".to_string()),
+            line_prefix: Some(true),
+            ..Default::default()
+        };
+        assert_matches(&regex("ab", false, false, &options), &["ab"], &["abc"]);
+    }
+
+    #[test]
+    fn map_pattern_maps_literals() {
+        // Swap the case of ASCII letters and map `.` to `!`
+        let mut byte_map: Vec<u8> = (0..=255).collect();
+        for c in b'a'..=b'z' {
+            byte_map[c as usize] = c.to_ascii_uppercase();
+            byte_map[c.to_ascii_uppercase() as usize] = c;
+        }
+        byte_map[b'.' as usize] = b'!';
+        let map = |pattern| map_pattern(pattern, &byte_map);
+
+        assert_eq!(map("ab1"), r"\x41\x421");
+        assert_eq!(map(r"\x61\x{41}\x30"), r"\x41\x61\x30");
+        assert_eq!(map(r"a\.b\+"), r"\x41\x21\x42\+");
+        assert_eq!(map(r"\d\w+\b"), r"\d\w+\b");
+        assert_eq!(map(r"\p{Greek}\pL"), r"\p{Greek}\pL");
+        assert_eq!(
+            map("[a-c][^x][[:alpha:]]"),
+            r"[\x41-\x43][^\x58][[:alpha:]]"
+        );
+        assert_eq!(
+            map("(?i:a)(?P<name>a){2,3}"),
+            r"(?i:\x41)(?P<name>\x41){2,3}"
+        );
+        assert_eq!(map("é.a"), r"é.\x41");
+    }
+
+    fn assert_matches(regex: &Regex, matching: &[&str], other: &[&str]) {
+        for text in matching {
+            assert!(
+                regex.is_match(text.as_bytes()),
+                "{} should match {text:?}",
+                regex.as_str()
+            );
+        }
+        for text in other {
+            assert!(
+                !regex.is_match(text.as_bytes()),
+                "{} should not match {text:?}",
+                regex.as_str()
+            );
+        }
+    }
+
+    /// Unicode `.` with ASCII-only case folding.
+    fn ascii_case() -> SearchOptions {
+        SearchOptions {
+            unicode_dot: Some(true),
+            unicode_case: Some(false),
+            ..Default::default()
+        }
+    }
+
+    /// Byte-mode `.` with Unicode case folding.
+    fn unicode_case() -> SearchOptions {
+        SearchOptions {
+            unicode_dot: Some(false),
+            unicode_case: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn simple_case_folding() {
+        for unicode in [false, true] {
+            let folded = |pattern| regex(pattern, unicode, true, &SearchOptions::default());
+            assert_matches(&folded("^ß$"), &["ß"], &["SS", "ss"]);
+            assert_matches(&folded("^SS$"), &["ss"], &["ß", "ẞ"]);
+            assert_matches(&folded("^İ$"), &["İ"], &["i", "I"]);
+            assert_matches(&folded("^i$"), &["I"], &["İ", "ı"]);
+            assert_matches(&folded("^ı$"), &["ı"], &["I", "i"]);
+            assert_matches(&folded("^I$"), &["i"], &["ı", "İ"]);
+        }
+
+        let unicode = |pattern| regex(pattern, true, true, &SearchOptions::default());
+        assert_matches(&unicode("^ß$"), &["ẞ"], &[]);
+        assert_matches(&unicode("^ẞ$"), &["ß"], &[]);
+        let ascii = |pattern| regex(pattern, false, true, &SearchOptions::default());
+        assert_matches(&ascii("^ß$"), &[], &["ẞ"]);
+        assert_matches(&ascii("^ẞ$"), &["ẞ"], &["ß"]);
+    }
+
+    #[test]
+    fn fold_literals() {
+        let ascii = |pattern| regex(pattern, false, true, &ascii_case());
+        assert_matches(&ascii("k"), &["k", "K"], &["\u{212A}"]);
+        assert_matches(&ascii("é"), &["é"], &["É"]);
+        assert_matches(&ascii("^.$"), &["é", "K"], &["ab"]);
+
+        let unicode = |pattern| regex(pattern, false, true, &unicode_case());
+        assert_matches(&unicode("k"), &["k", "K", "\u{212A}"], &["x"]);
+        assert_matches(&unicode("é"), &["é", "É"], &["e"]);
+        assert_matches(&unicode("^.$"), &["a"], &["é"]);
+    }
+
+    #[test]
+    fn fold_classes() {
+        let ascii = |pattern| regex(pattern, false, true, &ascii_case());
+        assert_matches(&ascii("^[a-c]$"), &["a", "B"], &["d", "D"]);
+        assert_matches(&ascii("^[^a-z]$"), &["1", "é"], &["a", "A"]);
+        assert_matches(&ascii(r"\p{Lu}"), &["a", "É"], &["é", "1"]);
+        assert_matches(&ascii("^[à-â]$"), &["á"], &["Á"]);
+
+        // Byte-mode classes only allow non-ASCII characters under `(?u)`
+        let unicode = |pattern| regex(pattern, false, true, &unicode_case());
+        assert_matches(&unicode("^(?u:[à-â])$"), &["á", "Á"], &["a"]);
+        assert_matches(&unicode("^(?u:[^é])$"), &["e"], &["é", "É"]);
+        assert_matches(&unicode(r"(?u:\p{Lu})"), &["a", "é", "É"], &["1"]);
+    }
+
+    #[test]
+    fn fold_escapes_and_inline_flags() {
+        let ascii = |pattern| regex(pattern, false, true, &ascii_case());
+        assert_matches(&ascii(r"^\x41$"), &["a", "A"], &["b"]);
+        assert_matches(&ascii(r"^a\.b$"), &["A.B"], &["AxB"]);
+        assert_matches(&ascii(r"^\d$"), &["1"], &["a"]);
+        assert_matches(&ascii("(?-i:a)b"), &["aB"], &["Ab"]);
+        assert_matches(&ascii("(?-i)a(?i:b)"), &["aB"], &["Ab"]);
+
+        // Without case-insensitivity the pattern is left alone
+        assert_matches(&regex("k", false, false, &unicode_case()), &["k"], &["K"]);
+    }
 }
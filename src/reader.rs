@@ -0,0 +1,337 @@
+// Copyright (c) 2026 Piet Hein Schouten
+// SPDX-License-Identifier: MIT
+
+use std::borrow::Cow;
+
+use memchr::{memchr, memrchr};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+use crate::{
+    add_line_details, compile_search, is_excluded_line, line_terminator, map_content,
+    skips_invalid_utf8, trim_line_ending, CompiledSearch, ExcludeFilter, FilePatternMatches,
+    LineMatch, MatchMode, MatchRange, PatternMatch, SearchOptions,
+};
+
+// ============================================================================
+// Incremental search of pushed chunks
+// ============================================================================
+
+/// Searches content that arrives in chunks, such as stdin or another readable
+/// stream, one complete line at a time. Partial lines are buffered until the
+/// rest of the line arrives, and line numbers and byte offsets count from the
+/// start of the first chunk.
+///
+/// Each line is matched on its own, so patterns cannot match across lines.
+/// `exclude_patterns`, `min_line_length`, the pattern rewriting options,
+/// `include_text`, `max_line_length`, `snippet_radius`, `include_match_bytes`,
+/// `captures`, `column_unit`, `tab_width`, `invert_match`, `max_results`,
+/// `zero_based`, `line_terminator`, `guard`, `guard_window`, `scope`,
+/// `field_delimiter` and `byte_map` apply; the remaining options are ignored.
+/// The guard and scope only see the current line.
+#[napi]
+pub struct ReaderSearch {
+    compiled: CompiledSearch,
+    mode: MatchMode,
+    options: SearchOptions,
+    /// Bytes of the current line that have not been terminated yet
+    pending: Vec<u8>,
+    /// Byte offset of the start of `pending` in the content
+    pending_offset: usize,
+    /// Number of lines processed so far
+    lines_seen: u32,
+    /// Number of line terminators seen so far
+    terminators: u32,
+    /// Number of lines returned from `push` and `finish`
+    lines_reported: u32,
+    total_matches: u32,
+    /// Whether `max_results` left a selected line out
+    truncated: bool,
+    /// Frequency and line numbers for each pattern, in input order
+    pattern_stats: Vec<(u32, Vec<u32>)>,
+    finished: bool,
+}
+
+#[napi]
+impl ReaderSearch {
+    /// Compile `patterns` for an incremental search combined according to
+    /// `mode`. Throws if any pattern fails to compile.
+    #[napi(constructor)]
+    pub fn new(
+        patterns: Vec<String>,
+        mode: MatchMode,
+        unicode: bool,
+        case_insensitive: bool,
+        options: Option<SearchOptions>,
+    ) -> napi::Result<Self> {
+        let options = options.unwrap_or_default();
+        let compiled = compile_search(&patterns, unicode, case_insensitive, &options)?;
+
+        Ok(ReaderSearch {
+            pattern_stats: vec![(0, Vec::new()); compiled.regexes.len()],
+            compiled,
+            mode,
+            options,
+            pending: Vec::new(),
+            pending_offset: 0,
+            lines_seen: 0,
+            terminators: 0,
+            lines_reported: 0,
+            total_matches: 0,
+            truncated: false,
+            finished: false,
+        })
+    }
+
+    /// Search the lines completed by `chunk` and return the ones that match,
+    /// in line order. A trailing partial line is kept for the next chunk.
+    #[napi]
+    pub fn push(&mut self, chunk: Buffer) -> Vec<LineMatch> {
+        if self.finished {
+            return Vec::new();
+        }
+
+        let terminator = line_terminator(&self.options);
+        let Some(last) = memrchr(terminator, &chunk) else {
+            self.pending.extend_from_slice(&chunk);
+            return Vec::new();
+        };
+
+        let mut content = std::mem::take(&mut self.pending);
+        content.extend_from_slice(&chunk[..=last]);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(i) = memchr(terminator, &content[start..]) {
+            lines.extend(self.search_line(&content[start..start + i], self.pending_offset + start));
+            start += i + 1;
+            self.terminators += 1;
+        }
+
+        self.pending_offset += content.len();
+        self.pending = chunk[last + 1..].to_vec();
+        lines
+    }
+
+    /// Search the final line if the content did not end with a line
+    /// terminator. Call once all chunks have been pushed; later pushes are
+    /// ignored.
+    #[napi]
+    pub fn finish(&mut self) -> Vec<LineMatch> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.finished = true;
+
+        let line = std::mem::take(&mut self.pending);
+        if line.is_empty() {
+            return Vec::new();
+        }
+        self.search_line(&line, self.pending_offset)
+            .into_iter()
+            .collect()
+    }
+
+    /// Per-pattern results for everything pushed so far, combined according to
+    /// `mode` like `search_buffer`. Returns an empty array if the content does
+    /// not match. `lines` is never included; matching lines are returned by
+    /// `push` and `finish` instead.
+    #[napi]
+    pub fn result(&self) -> Vec<FilePatternMatches> {
+        let patterns: Vec<PatternMatch> = self
+            .pattern_stats
+            .iter()
+            .enumerate()
+            .filter(|(_, (frequency, _))| *frequency > 0)
+            .map(|(idx, (frequency, line_numbers))| PatternMatch {
+                pattern_index: idx as u32,
+                frequency: *frequency,
+                line_numbers: line_numbers.clone(),
+            })
+            .collect();
+
+        let matched = match self.mode {
+            MatchMode::And => patterns.len() == self.pattern_stats.len(),
+            MatchMode::Or => !patterns.is_empty(),
+        };
+        if !matched {
+            return Vec::new();
+        }
+
+        let mut matched_lines: Vec<u32> = patterns
+            .iter()
+            .flat_map(|p| p.line_numbers.iter().copied())
+            .collect();
+        matched_lines.sort_unstable();
+        matched_lines.dedup();
+
+        vec![FilePatternMatches {
+            file_path: String::new(),
+            total_lines: self.terminators + 1,
+            total_matches: self.total_matches,
+            matching_lines: matched_lines.len() as u32,
+            patterns,
+            lines: None,
+            line_ranges: None,
+            partial: false,
+            truncated: self.truncated,
+            text_truncated: None,
+            error: None,
+            stats: None,
+            input_index: None,
+        }]
+    }
+
+    /// Match one complete line starting at byte `offset` and update the
+    /// per-pattern results. Returns the line if it is selected.
+    fn search_line(&mut self, line: &[u8], offset: usize) -> Option<LineMatch> {
+        self.lines_seen += 1;
+
+        let mapped = map_content(Cow::Borrowed(line), &self.compiled);
+        let line = &*mapped;
+        let terminator = line_terminator(&self.options);
+        let text = trim_line_ending(line, terminator);
+        let max_lines = self.options.max_results.unwrap_or(u32::MAX);
+        if self.lines_reported >= max_lines {
+            // Only the first line past the limit needs checking
+            if !self.truncated {
+                self.truncated = self.selects(text);
+            }
+            return None;
+        }
+
+        let min_line_length = self.options.min_line_length.unwrap_or(0);
+        let skip_invalid = skips_invalid_utf8(&self.options);
+        if is_excluded_line(
+            text,
+            &self.compiled.exclude_regexes,
+            min_line_length,
+            skip_invalid,
+        ) {
+            return None;
+        }
+
+        let line_number = self.lines_seen - self.options.zero_based.unwrap_or(false) as u32;
+        let mut matches = Vec::new();
+        let mut matched_patterns = Vec::new();
+
+        let mut filter = ExcludeFilter::new(&self.compiled, &self.options);
+        if self.options.invert_match.unwrap_or(false) {
+            let matched: Vec<bool> = self
+                .compiled
+                .regexes
+                .iter()
+                .map(|r| filter.line_matches(r, text, 0..text.len()))
+                .collect();
+            let selected = match self.mode {
+                MatchMode::And => !matched.iter().all(|&m| m),
+                MatchMode::Or => !matched.iter().any(|&m| m),
+            };
+            if !selected {
+                return None;
+            }
+
+            for (idx, (stats, &is_match)) in self.pattern_stats.iter_mut().zip(&matched).enumerate()
+            {
+                if is_match {
+                    matched_patterns.push(idx as u32);
+                } else {
+                    stats.0 += 1;
+                    stats.1.push(line_number);
+                }
+            }
+            self.total_matches += 1;
+        } else {
+            let mut ranges = Vec::new();
+            for (idx, regex) in self.compiled.regexes.iter().enumerate() {
+                let before = ranges.len();
+                ranges.extend(
+                    regex
+                        .find_iter(text)
+                        .filter(|m| !filter.rejects(text, m.range()))
+                        .map(|m| (m.start(), m.end())),
+                );
+
+                let found = (ranges.len() - before) as u32;
+                if found > 0 {
+                    let stats = &mut self.pattern_stats[idx];
+                    stats.0 += found;
+                    stats.1.push(line_number);
+                    matched_patterns.push(idx as u32);
+                }
+            }
+            if ranges.is_empty() {
+                return None;
+            }
+
+            ranges.sort_unstable();
+            if self.options.dedup_positions.unwrap_or(true) {
+                ranges.dedup();
+                let mut starts: Vec<usize> = ranges.iter().map(|&(start, _)| start).collect();
+                starts.dedup();
+                self.total_matches += starts.len() as u32;
+            } else {
+                self.total_matches += ranges.len() as u32;
+            }
+
+            matches = ranges
+                .into_iter()
+                .map(|(start, end)| MatchRange {
+                    start: start as u32,
+                    end: end as u32,
+                    end_line: None,
+                    end_column: None,
+                    bytes: None,
+                    captures: None,
+                })
+                .collect();
+        }
+
+        self.lines_reported += 1;
+        let column = matches.first().map_or(0, |m: &MatchRange| m.start);
+
+        let mut line_match = LineMatch::new(
+            line_number,
+            (offset + column as usize) as i64,
+            column,
+            matches,
+            matched_patterns,
+        );
+        add_line_details(
+            &mut line_match,
+            line,
+            text,
+            &self.compiled.regexes,
+            &self.options,
+        );
+        Some(line_match)
+    }
+
+    /// Whether `text` would be selected, without updating any results.
+    fn selects(&self, text: &[u8]) -> bool {
+        let min_line_length = self.options.min_line_length.unwrap_or(0);
+        let skip_invalid = skips_invalid_utf8(&self.options);
+        if is_excluded_line(
+            text,
+            &self.compiled.exclude_regexes,
+            min_line_length,
+            skip_invalid,
+        ) {
+            return false;
+        }
+
+        let mut filter = ExcludeFilter::new(&self.compiled, &self.options);
+        let mut matched = self
+            .compiled
+            .regexes
+            .iter()
+            .map(|r| filter.line_matches(r, text, 0..text.len()));
+        if !self.options.invert_match.unwrap_or(false) {
+            return matched.any(|m| m);
+        }
+        match self.mode {
+            MatchMode::And => !matched.all(|m| m),
+            MatchMode::Or => !matched.any(|m| m),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2026 Piet Hein Schouten
+// SPDX-License-Identifier: MIT
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use globset::GlobBuilder;
+use ignore::{DirEntry, WalkBuilder};
+
+use crate::{SearchError, SearchOptions};
+
+// ============================================================================
+// Directory traversal
+// ============================================================================
+
+/// Collect the files under `root` whose path relative to `root` matches
+/// `glob`, sorted by path. Entries that cannot be read, including symbolic
+/// links that form a cycle, are skipped, as are files outside
+/// `options.min_file_size` and `options.modified_after`.
+pub(crate) fn collect_files(
+    root: &str,
+    glob: &str,
+    options: &SearchOptions,
+) -> Result<Vec<String>, SearchError> {
+    let matcher = GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .map_err(SearchError::InvalidGlob)?
+        .compile_matcher();
+
+    let respect_gitignore = options.respect_gitignore.unwrap_or(true);
+
+    // Ignored and hidden paths are pruned during traversal, so ignored
+    // directories are never descended into.
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(false)
+        .hidden(!options.hidden.unwrap_or(false))
+        .follow_links(options.follow_symlinks.unwrap_or(false))
+        .ignore(respect_gitignore)
+        .parents(respect_gitignore)
+        .git_ignore(respect_gitignore)
+        .git_global(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .sort_by_file_name(|a, b| a.cmp(b));
+
+    if let Some(max_file_size) = options.max_file_size {
+        builder.max_filesize(Some(max_file_size.max(0) as u64));
+    }
+
+    let mut files = Vec::new();
+
+    for entry in builder.build() {
+        let Ok(entry) = entry else { continue };
+
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+
+        let path = entry.path();
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+
+        if matcher.is_match(relative) && metadata_matches(&entry, options) {
+            if let Some(path) = path.to_str() {
+                files.push(path.to_string());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Whether `entry` passes the metadata filters in `options`. The metadata is
+/// only read if a filter is set.
+fn metadata_matches(entry: &DirEntry, options: &SearchOptions) -> bool {
+    if options.min_file_size.is_none() && options.modified_after.is_none() {
+        return true;
+    }
+    let Ok(metadata) = entry.metadata() else {
+        return false;
+    };
+
+    if let Some(min_file_size) = options.min_file_size {
+        if metadata.len() < min_file_size.max(0) as u64 {
+            return false;
+        }
+    }
+    if let Some(modified_after) = options.modified_after {
+        // Times too far in the future to represent exclude every file
+        let Ok(after) = Duration::try_from_secs_f64(modified_after.max(0.0) / 1000.0) else {
+            return false;
+        };
+        let threshold = UNIX_EPOCH + after;
+        if !metadata
+            .modified()
+            .is_ok_and(|modified| modified > threshold)
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Path of `file_path` relative to `root`, for a path returned by
+/// `collect_files`.
+pub(crate) fn relative_path<'a>(root: &str, file_path: &'a str) -> &'a str {
+    file_path
+        .strip_prefix(root)
+        .unwrap_or(file_path)
+        .trim_start_matches(['/', '\\'])
+}